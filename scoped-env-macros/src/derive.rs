@@ -0,0 +1,159 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::spanned::Spanned;
+use syn::{Attribute, Data, DeriveInput, Fields, GenericArgument, LitStr, PathArguments, Type};
+
+/// The options in a struct's `#[env(...)]` attributes.
+#[derive(Default)]
+struct StructOptions {
+    prefix: Option<LitStr>,
+}
+
+/// The options in a field's `#[env(...)]` attributes.
+#[derive(Default)]
+struct FieldOptions {
+    name: Option<LitStr>,
+    unset_if_none: bool,
+}
+
+fn struct_options(attrs: &[Attribute]) -> syn::Result<StructOptions> {
+    let mut options = StructOptions::default();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("env")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("prefix") {
+                options.prefix = Some(meta.value()?.parse()?);
+                Ok(())
+            } else {
+                Err(meta.error("expected `prefix = \"...\"`"))
+            }
+        })?;
+    }
+    Ok(options)
+}
+
+fn field_options(attrs: &[Attribute]) -> syn::Result<FieldOptions> {
+    let mut options = FieldOptions::default();
+    for attr in attrs.iter().filter(|attr| attr.path().is_ident("env")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                options.name = Some(meta.value()?.parse()?);
+                Ok(())
+            } else if meta.path.is_ident("unset_if_none") {
+                options.unset_if_none = true;
+                Ok(())
+            } else {
+                Err(meta.error("expected `name = \"...\"` or `unset_if_none`"))
+            }
+        })?;
+    }
+    Ok(options)
+}
+
+/// Whether {ty} is written as `Option<_>`.
+fn is_option(ty: &Type) -> bool {
+    let Type::Path(ty) = ty else {
+        return false;
+    };
+    let Some(segment) = ty.path.segments.last() else {
+        return false;
+    };
+    match &segment.arguments {
+        PathArguments::AngleBracketed(args) => {
+            segment.ident == "Option"
+                && args.args.len() == 1
+                && matches!(args.args[0], GenericArgument::Type(_))
+        }
+        _ => false,
+    }
+}
+
+pub fn expand(input: DeriveInput) -> syn::Result<TokenStream> {
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new(
+                    input.ident.span(),
+                    "#[derive(ScopedEnv)] needs a struct with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new(
+                input.ident.span(),
+                "#[derive(ScopedEnv)] can only be used on structs",
+            ))
+        }
+    };
+
+    let prefix = struct_options(&input.attrs)?
+        .prefix
+        .map(|prefix| prefix.value())
+        .unwrap_or_default();
+
+    let mut seen = Vec::new();
+    let mut entries = Vec::new();
+    for field in fields {
+        let options = field_options(&field.attrs)?;
+        let ident = field.ident.as_ref().expect("named fields have identifiers");
+        let name = match &options.name {
+            Some(name) => name.value(),
+            None => format!(
+                "{}{}",
+                prefix,
+                syn::ext::IdentExt::unraw(ident).to_string().to_uppercase()
+            ),
+        };
+        let span = options
+            .name
+            .as_ref()
+            .map_or_else(|| ident.span(), |name| name.span());
+        if seen.contains(&name) {
+            let message = format!("environment variable `{}` is set twice", name);
+            return Err(syn::Error::new(span, message));
+        }
+        seen.push(name.clone());
+        let name = LitStr::new(&name, span);
+
+        let entry = if is_option(&field.ty) {
+            let none = if options.unset_if_none {
+                quote! { ::std::option::Option::None => builder.remove(#name), }
+            } else {
+                quote! { ::std::option::Option::None => builder, }
+            };
+            quote! {
+                let builder = match &self.#ident {
+                    ::std::option::Option::Some(value) => {
+                        builder.set(#name, ::std::string::ToString::to_string(value))
+                    }
+                    #none
+                };
+            }
+        } else if options.unset_if_none {
+            return Err(syn::Error::new(
+                field.ty.span(),
+                "`unset_if_none` can only be used on `Option` fields",
+            ));
+        } else {
+            quote! {
+                let builder = builder.set(#name, ::std::string::ToString::to_string(&self.#ident));
+            }
+        };
+        entries.push(entry);
+    }
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics #ident #ty_generics #where_clause {
+            /// Sets every field of this struct as an environment
+            /// variable until the returned instance goes out of
+            /// scope.
+            pub fn apply(&self) -> ::scoped_env::ScopedEnvSet {
+                let builder = ::scoped_env::ScopedEnvSet::builder();
+                #(#entries)*
+                builder.apply()
+            }
+        }
+    })
+}
@@ -0,0 +1,46 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::ItemFn;
+
+pub fn expand(args: TokenStream, function: ItemFn) -> syn::Result<TokenStream> {
+    if !args.is_empty() {
+        return Err(syn::Error::new_spanned(
+            args,
+            "#[isolated] does not take any arguments",
+        ));
+    }
+
+    let ItemFn {
+        attrs,
+        vis,
+        sig,
+        block,
+    } = function;
+
+    let body = if sig.asyncness.is_some() {
+        // Taking the lock through `with_env_async` keeps it with the
+        // task rather than the thread that first polls it.
+        quote! {
+            ::scoped_env::with_env_async(
+                ::std::vec::Vec::<(&str, ::std::option::Option<&str>)>::new(),
+                async move {
+                    let _scoped_env_leak_guard = ::scoped_env::LeakGuard::new();
+                    #block
+                },
+            )
+            .await
+        }
+    } else {
+        quote! {
+            let _scoped_env_leak_guard = ::scoped_env::LeakGuard::new();
+            #block
+        }
+    };
+
+    Ok(quote! {
+        #(#attrs)*
+        #vis #sig {
+            #body
+        }
+    })
+}
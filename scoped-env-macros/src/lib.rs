@@ -0,0 +1,96 @@
+//! Procedural macros for the `scoped-env` crate. These are
+//! re-exported from `scoped-env` when its `macros` feature is
+//! enabled and should be used from there.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use syn::parse_macro_input;
+
+mod derive;
+mod isolated;
+mod scoped;
+
+/// Applies environment variables for the duration of a function,
+/// most usefully a test. Each entry is `NAME = value`, where the
+/// value is any expression whose type implements `AsRef<OsStr>`, or
+/// `NAME = unset` to remove the variable. Names that are not valid
+/// identifiers can be written as string literals.
+///
+/// The function body runs while holding the environment lock and
+/// every variable is restored when it returns, including when it
+/// returns early with `?`. On an `async fn` the body is run through
+/// `with_env_async`, which needs the `tokio` feature.
+///
+/// ```rust,ignore
+/// use scoped_env::scoped_env;
+///
+/// #[test]
+/// #[scoped_env(RUST_LOG = "debug", HOME = unset)]
+/// fn logs_at_debug() {
+///     assert_eq!(std::env::var("RUST_LOG").unwrap(), "debug");
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn scoped_env(args: TokenStream, item: TokenStream) -> TokenStream {
+    let entries = parse_macro_input!(args as scoped::Entries);
+    let function = parse_macro_input!(item as syn::ItemFn);
+    scoped::expand(entries, function).into()
+}
+
+/// Fails a test that leaves any environment variable added, changed
+/// or removed when it finishes, naming the offending variables. The
+/// function body runs with a `LeakGuard` held, which also holds the
+/// environment lock for the whole test. Changes made through
+/// `ScopedEnv` guards are undone before the check and never count
+/// as leaks. On an `async fn` the body is run through
+/// `with_env_async`, which needs the `tokio` feature.
+///
+/// ```rust,ignore
+/// #[test]
+/// #[scoped_env::isolated]
+/// fn does_not_leak() {
+///     let _c = scoped_env::ScopedEnv::set("HELLO", "WORLD");
+/// }
+/// ```
+#[proc_macro_attribute]
+pub fn isolated(args: TokenStream, item: TokenStream) -> TokenStream {
+    let args = proc_macro2::TokenStream::from(args);
+    let function = parse_macro_input!(item as syn::ItemFn);
+    isolated::expand(args, function)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Generates an `apply(&self) -> ScopedEnvSet` method that sets
+/// every field of a struct as an environment variable, using the
+/// `Display` form of each value. Each variable is named after its
+/// field in upper case, with the struct's `#[env(prefix = "...")]`
+/// in front. A field's `#[env(name = "...")]` gives its exact name
+/// instead, without the prefix.
+///
+/// A field of type `Option` that is `None` leaves its variable as it
+/// is, or removes it if the field is marked `#[env(unset_if_none)]`.
+///
+/// ```rust,ignore
+/// #[derive(scoped_env::ScopedEnv)]
+/// #[env(prefix = "APP_")]
+/// struct AppEnv {
+///     #[env(name = "DATABASE_URL")]
+///     db: String,
+///     port: u16,
+///     #[env(unset_if_none)]
+///     token: Option<String>,
+/// }
+///
+/// let env = AppEnv { db: "postgres://localhost".into(), port: 8080, token: None };
+/// let _c = env.apply();
+/// assert_eq!(std::env::var("APP_PORT").unwrap(), "8080");
+/// ```
+#[proc_macro_derive(ScopedEnv, attributes(env))]
+pub fn derive_scoped_env(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as syn::DeriveInput);
+    derive::expand(input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
@@ -0,0 +1,100 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::ext::IdentExt;
+use syn::parse::{Parse, ParseStream};
+use syn::punctuated::Punctuated;
+use syn::{Expr, Ident, ItemFn, LitStr, Token};
+
+/// The `NAME = value` list given to `#[scoped_env(...)]`.
+pub struct Entries(Punctuated<Entry, Token![,]>);
+
+pub struct Entry {
+    name: LitStr,
+    value: Option<Expr>,
+}
+
+impl Parse for Entries {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let entries = Punctuated::parse_terminated(input)?;
+        let mut seen = Vec::new();
+        for Entry { name, .. } in &entries {
+            if seen.contains(&name.value()) {
+                let message = format!("environment variable `{}` is set twice", name.value());
+                return Err(syn::Error::new(name.span(), message));
+            }
+            seen.push(name.value());
+        }
+
+        Ok(Entries(entries))
+    }
+}
+
+impl Parse for Entry {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let name = if input.peek(LitStr) {
+            input.parse()?
+        } else {
+            let ident = Ident::parse_any(input)?;
+            LitStr::new(&ident.unraw().to_string(), ident.span())
+        };
+        input.parse::<Token![=]>()?;
+
+        let fork = input.fork();
+        let is_unset = fork.parse::<Ident>().is_ok_and(|ident| ident == "unset")
+            && (fork.is_empty() || fork.peek(Token![,]));
+        let value = if is_unset {
+            input.parse::<Ident>()?;
+            None
+        } else {
+            Some(input.parse()?)
+        };
+
+        Ok(Entry { name, value })
+    }
+}
+
+pub fn expand(Entries(entries): Entries, function: ItemFn) -> TokenStream {
+    let vars = entries.iter().map(|Entry { name, value }| match value {
+        Some(value) => quote! {
+            (
+                #name,
+                ::std::option::Option::Some(
+                    ::std::convert::AsRef::<::std::ffi::OsStr>::as_ref(&#value).to_os_string(),
+                ),
+            )
+        },
+        None => quote! {
+            (#name, ::std::option::Option::None::<::std::ffi::OsString>)
+        },
+    });
+    // Spelled out so that an empty list still has an element type.
+    let vars = quote! {{
+        let vars: ::std::vec::Vec<(&str, ::std::option::Option<::std::ffi::OsString>)> =
+            ::std::vec![#(#vars),*];
+        vars
+    }};
+
+    let ItemFn {
+        attrs,
+        vis,
+        sig,
+        block,
+    } = function;
+    let body = if sig.asyncness.is_some() {
+        quote! {
+            ::scoped_env::with_env_async(#vars, async move #block).await
+        }
+    } else {
+        quote! {
+            let _scoped_env = ::scoped_env::ScopedEnvSet::new(#vars);
+            #block
+        }
+    };
+
+    quote! {
+        #(#attrs)*
+        #vis #sig {
+            #body
+        }
+    }
+}
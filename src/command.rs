@@ -0,0 +1,111 @@
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::process::Command;
+
+/// Hands scoped environment variables to a child process instead of
+/// the current one. Anything that is a list of `(name, value)`
+/// pairs can be applied, most usefully a
+/// [`ScopedEnvSetBuilder`](crate::ScopedEnvSetBuilder), which has
+/// never touched the current process, or a
+/// [`ScopedEnvSet`](crate::ScopedEnvSet). A value of `None` removes
+/// the variable from the child's environment.
+///
+/// ```rust
+/// use scoped_env::{CommandEnvExt, ScopedEnvSet};
+/// use std::process::Command;
+///
+/// let vars = ScopedEnvSet::builder().set("RUST_LOG", "debug").remove("HOME");
+/// let mut cmd = Command::new("my-cli");
+/// cmd.apply_scoped(&vars);
+/// assert!(std::env::var("RUST_LOG").is_err());
+/// ```
+pub trait CommandEnvExt {
+    /// Sets and removes every variable in {vars} for the child
+    /// process, on top of the environment it would otherwise
+    /// inherit.
+    fn apply_scoped<S>(&mut self, vars: &S) -> &mut Self
+    where
+        S: AsRef<[(OsString, Option<OsString>)]> + ?Sized;
+
+    /// Starts the child process from an empty environment, passes
+    /// through only the variables named in {allowlist} from the
+    /// current process, then applies {vars} on top.
+    fn apply_clean<S, K>(&mut self, vars: &S, allowlist: &[K]) -> &mut Self
+    where
+        S: AsRef<[(OsString, Option<OsString>)]> + ?Sized,
+        K: AsRef<OsStr>;
+}
+
+impl CommandEnvExt for Command {
+    fn apply_scoped<S>(&mut self, vars: &S) -> &mut Self
+    where
+        S: AsRef<[(OsString, Option<OsString>)]> + ?Sized,
+    {
+        for (name, value) in vars.as_ref() {
+            match value {
+                Some(value) => self.env(name, value),
+                None => self.env_remove(name),
+            };
+        }
+        self
+    }
+
+    fn apply_clean<S, K>(&mut self, vars: &S, allowlist: &[K]) -> &mut Self
+    where
+        S: AsRef<[(OsString, Option<OsString>)]> + ?Sized,
+        K: AsRef<OsStr>,
+    {
+        self.env_clear();
+        for name in allowlist {
+            if let Some(value) = env::var_os(name.as_ref()) {
+                self.env(name.as_ref(), value);
+            }
+        }
+        self.apply_scoped(vars)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ScopedEnv, ScopedEnvSet};
+
+    #[test]
+    fn does_apply_to_the_command_only() {
+        let vars = ScopedEnvSet::builder()
+            .set("COMMAND_A", "1")
+            .remove("COMMAND_B");
+        let mut cmd = Command::new("true");
+        cmd.apply_scoped(&vars);
+
+        let envs: Vec<_> = cmd.get_envs().collect();
+        assert_eq!(
+            envs,
+            vec![
+                (OsStr::new("COMMAND_A"), Some(OsStr::new("1"))),
+                (OsStr::new("COMMAND_B"), None),
+            ]
+        );
+        assert_eq!(env::var_os("COMMAND_A"), None);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn does_start_clean() {
+        let _c = ScopedEnv::set("COMMAND_C", "allowed");
+        let _d = ScopedEnv::set("COMMAND_D", "not allowed");
+        let vars = vec![("COMMAND_E".into(), Some("scoped".into()))];
+        let output = Command::new("/usr/bin/env")
+            .apply_clean(&vars, &["COMMAND_C"])
+            .output()
+            .unwrap();
+
+        let mut lines: Vec<_> = String::from_utf8(output.stdout)
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect();
+        lines.sort();
+        assert_eq!(lines, vec!["COMMAND_C=allowed", "COMMAND_E=scoped"]);
+    }
+}
@@ -0,0 +1,586 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::str::FromStr;
+
+use serde::de::{self, DeserializeOwned, IntoDeserializer, Visitor};
+
+use crate::{lock, overlay, EnvSnapshot, ParseVarError};
+
+/// Deserializes the environment of the currently running process,
+/// as seen through the [`overlay`](crate::overlay) of the current
+/// thread, with the default [`FromEnvOptions`].
+///
+/// ```rust
+/// use scoped_env::{from_env, ScopedEnv};
+///
+/// #[derive(serde::Deserialize)]
+/// struct Config {
+///     port: u16,
+///     hosts: Vec<String>,
+/// }
+///
+/// let _c = ScopedEnv::set("PORT", "8080");
+/// let _d = ScopedEnv::set("HOSTS", "a.example,b.example");
+/// let config: Config = from_env().unwrap();
+/// assert_eq!(config.port, 8080);
+/// assert_eq!(config.hosts, ["a.example", "b.example"]);
+/// ```
+pub fn from_env<T: DeserializeOwned>() -> Result<T, FromEnvError> {
+    FromEnvOptions::new().from_env()
+}
+
+/// Deserializes the variables captured in {snapshot} with the
+/// default [`FromEnvOptions`].
+pub fn from_snapshot<T: DeserializeOwned>(snapshot: &EnvSnapshot) -> Result<T, FromEnvError> {
+    FromEnvOptions::new().from_snapshot(snapshot)
+}
+
+/// How variable names map onto the fields of the deserialized type.
+///
+/// By default every variable is used, names are lower cased to
+/// match `snake_case` fields, `__` separates the name of a nested
+/// struct from its fields and sequences are separated by commas.
+///
+/// ```rust
+/// use scoped_env::{FromEnvOptions, ScopedEnv};
+///
+/// #[derive(serde::Deserialize)]
+/// struct Config {
+///     database: Database,
+/// }
+///
+/// #[derive(serde::Deserialize)]
+/// struct Database {
+///     url: String,
+/// }
+///
+/// let _c = ScopedEnv::set("APP_DATABASE__URL", "postgres://localhost");
+/// let config: Config = FromEnvOptions::new().prefix("APP_").from_env().unwrap();
+/// assert_eq!(config.database.url, "postgres://localhost");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FromEnvOptions {
+    prefix: String,
+    separator: String,
+    sequence_separator: char,
+    case_sensitive: bool,
+}
+
+impl FromEnvOptions {
+    /// Creates the default options.
+    pub fn new() -> Self {
+        Self {
+            prefix: String::new(),
+            separator: "__".into(),
+            sequence_separator: ',',
+            case_sensitive: false,
+        }
+    }
+
+    /// Only uses variables whose names start with {prefix}, which is
+    /// stripped before matching them to fields.
+    pub fn prefix<P: Into<String>>(mut self, prefix: P) -> Self {
+        self.prefix = prefix.into();
+        self
+    }
+
+    /// Uses {separator} between the name of a nested struct and the
+    /// names of its fields, instead of `__`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if {separator} is empty.
+    pub fn separator<S: Into<String>>(mut self, separator: S) -> Self {
+        let separator = separator.into();
+        assert!(!separator.is_empty(), "separator must not be empty");
+        self.separator = separator;
+        self
+    }
+
+    /// Splits the values of sequences on {separator} instead of a
+    /// comma.
+    pub fn sequence_separator(mut self, separator: char) -> Self {
+        self.sequence_separator = separator;
+        self
+    }
+
+    /// Matches variable names to fields exactly, instead of lower
+    /// casing them first.
+    pub fn case_sensitive(mut self, case_sensitive: bool) -> Self {
+        self.case_sensitive = case_sensitive;
+        self
+    }
+
+    /// Deserializes the environment of the currently running
+    /// process, as seen through the [`overlay`](crate::overlay) of
+    /// the current thread.
+    pub fn from_env<T: DeserializeOwned>(&self) -> Result<T, FromEnvError> {
+        let _lock = lock();
+        self.deserialize(overlay::vars_os())
+    }
+
+    /// Deserializes the variables captured in {snapshot}.
+    pub fn from_snapshot<T: DeserializeOwned>(
+        &self,
+        snapshot: &EnvSnapshot,
+    ) -> Result<T, FromEnvError> {
+        self.deserialize(
+            snapshot
+                .iter()
+                .map(|(name, value)| (name.to_os_string(), value.to_os_string())),
+        )
+    }
+
+    fn deserialize<T, I>(&self, vars: I) -> Result<T, FromEnvError>
+    where
+        T: DeserializeOwned,
+        I: IntoIterator<Item = (OsString, OsString)>,
+    {
+        let mut root = Node::new(self.prefix.trim_end_matches(&*self.separator).into());
+        for (name, value) in vars {
+            // Names that are not unicode cannot match a field.
+            let Some(key) = name
+                .to_str()
+                .and_then(|name| name.strip_prefix(&*self.prefix))
+            else {
+                continue;
+            };
+            if key.is_empty() {
+                continue;
+            }
+
+            let mut node = &mut root;
+            let mut consumed = self.prefix.len();
+            for (i, segment) in key.split(&*self.separator).enumerate() {
+                if i > 0 {
+                    consumed += self.separator.len();
+                }
+                consumed += segment.len();
+
+                let segment = if self.case_sensitive {
+                    segment.to_owned()
+                } else {
+                    segment.to_lowercase()
+                };
+                let path = &name.to_str().expect("checked above")[..consumed];
+                node = node
+                    .children
+                    .entry(segment)
+                    .or_insert_with(|| Node::new(path.into()));
+            }
+            node.value = Some(value);
+        }
+
+        T::deserialize(NodeDeserializer {
+            node: &root,
+            sequence_separator: self.sequence_separator,
+        })
+    }
+}
+
+impl Default for FromEnvOptions {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The reason the environment could not be deserialized.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FromEnvError {
+    message: String,
+}
+
+impl fmt::Display for FromEnvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for FromEnvError {}
+
+impl de::Error for FromEnvError {
+    fn custom<T: fmt::Display>(message: T) -> Self {
+        Self {
+            message: message.to_string(),
+        }
+    }
+}
+
+impl<E: fmt::Display> From<ParseVarError<E>> for FromEnvError {
+    fn from(err: ParseVarError<E>) -> Self {
+        de::Error::custom(err)
+    }
+}
+
+/// One segment of the variable names, holding the value of the
+/// variable that ends there, if any, and the segments that follow
+/// it.
+struct Node {
+    name: OsString,
+    value: Option<OsString>,
+    children: BTreeMap<String, Node>,
+}
+
+impl Node {
+    fn new(name: OsString) -> Self {
+        Self {
+            name,
+            value: None,
+            children: BTreeMap::new(),
+        }
+    }
+}
+
+struct NodeDeserializer<'a> {
+    node: &'a Node,
+    sequence_separator: char,
+}
+
+impl NodeDeserializer<'_> {
+    fn name(&self) -> &OsStr {
+        &self.node.name
+    }
+
+    fn value<E>(&self) -> Result<&str, ParseVarError<E>> {
+        let value = self
+            .node
+            .value
+            .as_ref()
+            .ok_or_else(|| ParseVarError::NotPresent {
+                name: self.name().into(),
+            })?;
+        value.to_str().ok_or_else(|| ParseVarError::NotUnicode {
+            name: self.name().into(),
+            value: value.clone(),
+        })
+    }
+
+    fn parse<U>(&self) -> Result<U, FromEnvError>
+    where
+        U: FromStr,
+        U::Err: fmt::Display,
+    {
+        let value = self.value::<U::Err>()?;
+        value.parse().map_err(|source| {
+            FromEnvError::from(ParseVarError::Invalid {
+                name: self.name().into(),
+                value: value.into(),
+                source,
+            })
+        })
+    }
+}
+
+macro_rules! deserialize_parsed {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, FromEnvError> {
+                visitor.$visit(self.parse()?)
+            }
+        )*
+    };
+}
+
+impl<'de> de::Deserializer<'de> for NodeDeserializer<'_> {
+    type Error = FromEnvError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, FromEnvError> {
+        if self.node.children.is_empty() && self.node.value.is_some() {
+            self.deserialize_string(visitor)
+        } else {
+            self.deserialize_map(visitor)
+        }
+    }
+
+    deserialize_parsed! {
+        deserialize_bool => visit_bool,
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_i128 => visit_i128,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_u128 => visit_u128,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+        deserialize_char => visit_char,
+    }
+
+    fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, FromEnvError> {
+        visitor.visit_str(self.value::<FromEnvError>()?)
+    }
+
+    fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, FromEnvError> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, FromEnvError> {
+        visitor.visit_bytes(self.value::<FromEnvError>()?.as_bytes())
+    }
+
+    fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, FromEnvError> {
+        self.deserialize_bytes(visitor)
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, FromEnvError> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, FromEnvError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_unit_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, FromEnvError> {
+        visitor.visit_unit()
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        visitor: V,
+    ) -> Result<V::Value, FromEnvError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, FromEnvError> {
+        let value = self.value::<FromEnvError>()?;
+        let elements: Vec<Node> = if value.is_empty() {
+            Vec::new()
+        } else {
+            value
+                .split(self.sequence_separator)
+                .map(|element| Node {
+                    name: self.name().into(),
+                    value: Some(element.into()),
+                    children: BTreeMap::new(),
+                })
+                .collect()
+        };
+
+        let sequence_separator = self.sequence_separator;
+        visitor.visit_seq(de::value::SeqDeserializer::new(elements.iter().map(
+            |node| NodeDeserializer {
+                node,
+                sequence_separator,
+            },
+        )))
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, FromEnvError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_tuple_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, FromEnvError> {
+        self.deserialize_seq(visitor)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, FromEnvError> {
+        let sequence_separator = self.sequence_separator;
+        visitor.visit_map(de::value::MapDeserializer::new(
+            self.node.children.iter().map(|(key, node)| {
+                (
+                    key.as_str(),
+                    NodeDeserializer {
+                        node,
+                        sequence_separator,
+                    },
+                )
+            }),
+        ))
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, FromEnvError> {
+        self.deserialize_map(visitor)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, FromEnvError> {
+        let value: de::value::StrDeserializer<'_, FromEnvError> =
+            self.value::<FromEnvError>()?.into_deserializer();
+        visitor.visit_enum(value)
+    }
+
+    fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, FromEnvError> {
+        self.deserialize_str(visitor)
+    }
+
+    fn deserialize_ignored_any<V: Visitor<'de>>(
+        self,
+        visitor: V,
+    ) -> Result<V::Value, FromEnvError> {
+        visitor.visit_unit()
+    }
+}
+
+impl<'de> IntoDeserializer<'de, FromEnvError> for NodeDeserializer<'_> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MapEnv, ScopedEnv};
+    use serde::Deserialize;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Config {
+        port: u16,
+        debug: bool,
+        hosts: Vec<String>,
+        token: Option<String>,
+        level: Level,
+        database: Database,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    enum Level {
+        Info,
+        Debug,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Database {
+        url: String,
+        pool_size: Option<u32>,
+    }
+
+    fn snapshot(vars: &[(&str, &str)]) -> EnvSnapshot {
+        let _lock = crate::lock();
+        let _c: Vec<_> = vars
+            .iter()
+            .map(|(name, value)| ScopedEnv::set(*name, *value))
+            .collect();
+        EnvSnapshot::capture()
+    }
+
+    #[test]
+    fn does_deserialize_nested_config() {
+        let snapshot = snapshot(&[
+            ("DE_A_PORT", "8080"),
+            ("DE_A_DEBUG", "true"),
+            ("DE_A_HOSTS", "a.example,b.example"),
+            ("DE_A_LEVEL", "debug"),
+            ("DE_A_DATABASE__URL", "postgres://localhost"),
+        ]);
+        let config: Config = FromEnvOptions::new()
+            .prefix("DE_A_")
+            .from_snapshot(&snapshot)
+            .unwrap();
+
+        assert_eq!(
+            config,
+            Config {
+                port: 8080,
+                debug: true,
+                hosts: vec!["a.example".into(), "b.example".into()],
+                token: None,
+                level: Level::Debug,
+                database: Database {
+                    url: "postgres://localhost".into(),
+                    pool_size: None,
+                },
+            }
+        );
+    }
+
+    #[test]
+    fn does_read_through_the_overlay() {
+        #[derive(Deserialize)]
+        struct Config {
+            de_b_name: String,
+        }
+
+        let _c = overlay::set("DE_B_NAME", "overlaid");
+        let config: Config = from_env().unwrap();
+        assert_eq!(config.de_b_name, "overlaid");
+    }
+
+    #[test]
+    fn does_honour_the_options() {
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[allow(non_snake_case)]
+        struct Config {
+            Outer: Inner,
+            list: Vec<u8>,
+        }
+
+        #[derive(Debug, PartialEq, Deserialize)]
+        #[allow(non_snake_case)]
+        struct Inner {
+            Value: String,
+        }
+
+        let vars = MapEnv::new()
+            .with("DE_C.Outer.Value", "hello")
+            .with("DE_C.list", "1;2;3")
+            .with("DE_C.Outer.value", "ignored");
+        let config: Config = FromEnvOptions::new()
+            .prefix("DE_C.")
+            .separator(".")
+            .sequence_separator(';')
+            .case_sensitive(true)
+            .deserialize(crate::EnvProvider::iter(&vars))
+            .unwrap();
+
+        assert_eq!(
+            config,
+            Config {
+                Outer: Inner {
+                    Value: "hello".into()
+                },
+                list: vec![1, 2, 3],
+            }
+        );
+    }
+
+    #[test]
+    fn errors_name_the_variable() {
+        #[derive(Debug, Deserialize)]
+        struct Config {
+            #[allow(dead_code)]
+            port: u16,
+        }
+
+        let vars = MapEnv::new().with("DE_D_PORT", "eighty");
+        let err = FromEnvOptions::new()
+            .prefix("DE_D_")
+            .deserialize::<Config, _>(crate::EnvProvider::iter(&vars))
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            r#"value "eighty" for environment variable "DE_D_PORT" is invalid: invalid digit found in string"#
+        );
+    }
+}
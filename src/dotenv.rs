@@ -0,0 +1,402 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use crate::error;
+use crate::InterpolationError;
+
+/// The reason a `.env` file could not be loaded.
+#[derive(Debug)]
+pub enum DotenvError {
+    /// The file could not be read.
+    Io(io::Error),
+    /// The file is not valid dotenv syntax. Lines and columns
+    /// both start at 1.
+    Parse {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    /// A `${...}` reference in the file could not be expanded.
+    Interpolation(InterpolationError),
+}
+
+impl fmt::Display for DotenvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DotenvError::Io(err) => write!(f, "failed to read .env file: {}", err),
+            DotenvError::Parse {
+                line,
+                column,
+                message,
+            } => write!(f, "line {}, column {}: {}", line, column, message),
+            DotenvError::Interpolation(err) => err.fmt(f),
+        }
+    }
+}
+
+impl Error for DotenvError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            DotenvError::Io(err) => Some(err),
+            DotenvError::Parse { .. } => None,
+            DotenvError::Interpolation(err) => Some(err),
+        }
+    }
+}
+
+impl From<io::Error> for DotenvError {
+    fn from(err: io::Error) -> Self {
+        DotenvError::Io(err)
+    }
+}
+
+impl From<InterpolationError> for DotenvError {
+    fn from(err: InterpolationError) -> Self {
+        DotenvError::Interpolation(err)
+    }
+}
+
+/// Parses the contents of a `.env` file into its entries, in the
+/// order they appear.
+pub(crate) fn parse(source: &str) -> Result<Vec<(String, String)>, DotenvError> {
+    parse_with(source, false)
+}
+
+/// Parses the contents of a `.env` file like [`parse`], but writes
+/// every `$` that is meant literally, from a single quoted value or
+/// a `\$` escape, as `$$` so the values can be interpolated.
+pub(crate) fn parse_template(source: &str) -> Result<Vec<(String, String)>, DotenvError> {
+    parse_with(source, true)
+}
+
+fn parse_with(source: &str, template: bool) -> Result<Vec<(String, String)>, DotenvError> {
+    let mut parser = Parser {
+        chars: source.chars().collect(),
+        pos: 0,
+        line: 1,
+        column: 1,
+        template,
+    };
+    let mut entries = Vec::new();
+    while let Some(entry) = parser.entry()? {
+        entries.push(entry);
+    }
+
+    Ok(entries)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+    column: usize,
+    /// Whether to write literal `$` characters as `$$`.
+    template: bool,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn bump(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, DotenvError> {
+        Err(DotenvError::Parse {
+            line: self.line,
+            column: self.column,
+            message: message.into(),
+        })
+    }
+
+    fn skip_blanks(&mut self) {
+        while matches!(self.peek(), Some(' ') | Some('\t')) {
+            self.bump();
+        }
+    }
+
+    fn skip_line(&mut self) {
+        while let Some(c) = self.bump() {
+            if c == '\n' {
+                break;
+            }
+        }
+    }
+
+    /// Consumes the rest of the line, which may only hold blanks
+    /// and a comment.
+    fn end_of_line(&mut self) -> Result<(), DotenvError> {
+        self.skip_blanks();
+        match self.peek() {
+            None | Some('\n') | Some('#') => {
+                self.skip_line();
+                Ok(())
+            }
+            Some('\r') if self.chars.get(self.pos + 1) == Some(&'\n') => {
+                self.skip_line();
+                Ok(())
+            }
+            Some(c) => self.error(format!("unexpected character {:?}", c)),
+        }
+    }
+
+    fn entry(&mut self) -> Result<Option<(String, String)>, DotenvError> {
+        loop {
+            self.skip_blanks();
+            match self.peek() {
+                None => return Ok(None),
+                Some('\n') | Some('\r') | Some('#') => self.skip_line(),
+                Some(_) => break,
+            }
+        }
+
+        let (line, column) = (self.line, self.column);
+        let mut name = self.name()?;
+        if name == "export" && matches!(self.peek(), Some(' ') | Some('\t')) {
+            self.skip_blanks();
+            if self.peek() != Some('=') {
+                name = self.name()?;
+            }
+        }
+
+        self.skip_blanks();
+        if self.peek() != Some('=') {
+            return self.error(format!("expected '=' after variable name {:?}", name));
+        }
+        self.bump();
+        self.skip_blanks();
+
+        let value = match self.peek() {
+            Some('\'') => self.single_quoted()?,
+            Some('"') => self.double_quoted()?,
+            _ => self.unquoted(),
+        };
+
+        // Names are limited to characters the environment accepts,
+        // but values can still hold a NUL character.
+        if let Err(err) = error::validate_value(name.as_ref(), value.as_ref()) {
+            return Err(DotenvError::Parse {
+                line,
+                column,
+                message: err.to_string(),
+            });
+        }
+        Ok(Some((name, value)))
+    }
+
+    fn name(&mut self) -> Result<String, DotenvError> {
+        let mut name = String::new();
+        while let Some(c) = self.peek() {
+            if c.is_ascii_alphanumeric() || c == '_' || c == '.' {
+                name.push(c);
+                self.bump();
+            } else {
+                break;
+            }
+        }
+
+        match name.chars().next() {
+            None => match self.peek() {
+                Some(c) => self.error(format!("unexpected character {:?}", c)),
+                None => self.error("expected a variable name"),
+            },
+            Some(c) if c.is_ascii_digit() => {
+                self.column -= name.chars().count();
+                self.error(format!("variable name {:?} starts with a digit", name))
+            }
+            Some(_) => Ok(name),
+        }
+    }
+
+    fn unquoted(&mut self) -> String {
+        let mut value = String::new();
+        while let Some(c) = self.peek() {
+            let after_blank = matches!(self.chars[self.pos - 1], ' ' | '\t');
+            if c == '\n' || (c == '#' && after_blank) {
+                break;
+            }
+            value.push(c);
+            self.bump();
+        }
+
+        self.skip_line();
+        value.trim_end().to_string()
+    }
+
+    /// Reads a single quoted value and the rest of its line.
+    fn single_quoted(&mut self) -> Result<String, DotenvError> {
+        let (line, column) = (self.line, self.column);
+        self.bump();
+        let mut value = String::new();
+        loop {
+            match self.bump() {
+                Some('\'') => {
+                    self.end_of_line()?;
+                    return Ok(value);
+                }
+                Some('$') if self.template => value.push_str("$$"),
+                Some(c) => value.push(c),
+                None => {
+                    return Err(DotenvError::Parse {
+                        line,
+                        column,
+                        message: "unterminated single quoted value".into(),
+                    })
+                }
+            }
+        }
+    }
+
+    /// Reads a double quoted value and the rest of its line.
+    fn double_quoted(&mut self) -> Result<String, DotenvError> {
+        let (line, column) = (self.line, self.column);
+        self.bump();
+        let mut value = String::new();
+        loop {
+            match self.bump() {
+                Some('"') => {
+                    self.end_of_line()?;
+                    return Ok(value);
+                }
+                Some('\\') => {
+                    let escaped = match self.peek() {
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some(c @ ('"' | '\\' | '\'' | '$')) => c,
+                        Some('\n' | '\r') => {
+                            self.column -= 1;
+                            return self.error("backslash at end of line");
+                        }
+                        Some(c) => {
+                            self.column -= 1;
+                            return self.error(format!("unknown escape sequence \\{}", c));
+                        }
+                        None => continue,
+                    };
+                    self.bump();
+                    if escaped == '$' && self.template {
+                        value.push('$');
+                    }
+                    value.push(escaped);
+                }
+                Some(c) => value.push(c),
+                None => {
+                    return Err(DotenvError::Parse {
+                        line,
+                        column,
+                        message: "unterminated double quoted value".into(),
+                    })
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(source: &str) -> Vec<(String, String)> {
+        parse(source).unwrap()
+    }
+
+    fn parse_error(source: &str) -> (usize, usize, String) {
+        match parse(source) {
+            Err(DotenvError::Parse {
+                line,
+                column,
+                message,
+            }) => (line, column, message),
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn does_parse_plain_entries() {
+        let source = "# a comment\n\nA=1\nexport B = two words  # trailing\r\nC= # empty\nD=a#b\n";
+        assert_eq!(
+            entries(source),
+            vec![
+                ("A".into(), "1".into()),
+                ("B".into(), "two words".into()),
+                ("C".into(), "".into()),
+                ("D".into(), "a#b".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn does_parse_quoted_entries() {
+        let source = "A='single \\n $raw' # comment\nB=\"tab\\there \\\"quoted\\\"\"\nC=\"line one\nline two\"\nexport = plain\n";
+        assert_eq!(
+            entries(source),
+            vec![
+                ("A".into(), "single \\n $raw".into()),
+                ("B".into(), "tab\there \"quoted\"".into()),
+                ("C".into(), "line one\nline two".into()),
+                ("export".into(), "plain".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn does_mark_literal_dollars_in_templates() {
+        let source = "A='${HOME} $$'\nB=\"\\${HOME} ${HOME}\"\nC=${HOME}\n";
+        assert_eq!(
+            parse_template(source).unwrap(),
+            vec![
+                ("A".into(), "$${HOME} $$$$".into()),
+                ("B".into(), "$${HOME} ${HOME}".into()),
+                ("C".into(), "${HOME}".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn does_report_line_and_column() {
+        assert_eq!(
+            parse_error("A=1\nB 2\n"),
+            (2, 3, "expected '=' after variable name \"B\"".into())
+        );
+        assert_eq!(
+            parse_error("A=1\n\nB=\"never\nclosed\n"),
+            (3, 3, "unterminated double quoted value".into())
+        );
+        assert_eq!(
+            parse_error("A='x' y\n"),
+            (1, 7, "unexpected character 'y'".into())
+        );
+        assert_eq!(
+            parse_error("A=\"\\q\"\n"),
+            (1, 4, "unknown escape sequence \\q".into())
+        );
+        assert_eq!(
+            parse_error("A=1\nB=\"one \\\ntwo\"\n"),
+            (2, 8, "backslash at end of line".into())
+        );
+        assert_eq!(
+            parse_error("1A=x\n"),
+            (1, 1, "variable name \"1A\" starts with a digit".into())
+        );
+        assert_eq!(
+            parse_error("A=1\n  N=\"a\0b\"\n"),
+            (
+                2,
+                3,
+                "value \"a\\0b\" for environment variable \"N\" contains a NUL character".into()
+            )
+        );
+    }
+}
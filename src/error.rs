@@ -0,0 +1,149 @@
+use std::error::Error;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+use std::path::PathBuf;
+
+/// The reason an environment variable could not be set or
+/// removed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScopedEnvError {
+    /// The variable name was empty.
+    EmptyName,
+    /// The variable name contained an ASCII equals sign `=`.
+    NameContainsEquals { name: OsString },
+    /// The variable name contained a NUL character.
+    NameContainsNul { name: OsString },
+    /// The value for the variable contained a NUL character.
+    ValueContainsNul { name: OsString, value: OsString },
+    /// An entry to add to a path list variable contained the
+    /// platform's path separator.
+    PathEntryContainsSeparator { name: OsString, entry: PathBuf },
+}
+
+impl ScopedEnvError {
+    /// The name of the variable that caused the error, if it had
+    /// one.
+    pub fn name(&self) -> Option<&OsStr> {
+        match self {
+            ScopedEnvError::EmptyName => None,
+            ScopedEnvError::NameContainsEquals { name }
+            | ScopedEnvError::NameContainsNul { name }
+            | ScopedEnvError::ValueContainsNul { name, .. }
+            | ScopedEnvError::PathEntryContainsSeparator { name, .. } => Some(name),
+        }
+    }
+}
+
+impl fmt::Display for ScopedEnvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScopedEnvError::EmptyName => f.write_str("environment variable name is empty"),
+            ScopedEnvError::NameContainsEquals { name } => write!(
+                f,
+                "environment variable name {:?} contains an equals sign",
+                name
+            ),
+            ScopedEnvError::NameContainsNul { name } => write!(
+                f,
+                "environment variable name {:?} contains a NUL character",
+                name
+            ),
+            ScopedEnvError::ValueContainsNul { name, value } => write!(
+                f,
+                "value {:?} for environment variable {:?} contains a NUL character",
+                value, name
+            ),
+            ScopedEnvError::PathEntryContainsSeparator { name, entry } => write!(
+                f,
+                "path entry {:?} for environment variable {:?} contains the path separator",
+                entry, name
+            ),
+        }
+    }
+}
+
+impl Error for ScopedEnvError {}
+
+/// The reason an environment variable could not be read as a typed
+/// value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseVarError<E> {
+    /// The variable was not set.
+    NotPresent { name: OsString },
+    /// The value of the variable was not valid unicode.
+    NotUnicode { name: OsString, value: OsString },
+    /// The value of the variable could not be parsed.
+    Invalid {
+        name: OsString,
+        value: String,
+        source: E,
+    },
+}
+
+impl<E> ParseVarError<E> {
+    /// The name of the variable that could not be read.
+    pub fn name(&self) -> &OsStr {
+        match self {
+            ParseVarError::NotPresent { name }
+            | ParseVarError::NotUnicode { name, .. }
+            | ParseVarError::Invalid { name, .. } => name,
+        }
+    }
+}
+
+impl<E: fmt::Display> fmt::Display for ParseVarError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseVarError::NotPresent { name } => {
+                write!(f, "environment variable {:?} is not set", name)
+            }
+            ParseVarError::NotUnicode { name, value } => write!(
+                f,
+                "value {:?} for environment variable {:?} is not valid unicode",
+                value, name
+            ),
+            ParseVarError::Invalid {
+                name,
+                value,
+                source,
+            } => write!(
+                f,
+                "value {:?} for environment variable {:?} is invalid: {}",
+                value, name, source
+            ),
+        }
+    }
+}
+
+impl<E: Error + 'static> Error for ParseVarError<E> {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ParseVarError::Invalid { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+pub(crate) fn validate_name(name: &OsStr) -> Result<(), ScopedEnvError> {
+    let bytes = name.as_encoded_bytes();
+    if bytes.is_empty() {
+        Err(ScopedEnvError::EmptyName)
+    } else if bytes.contains(&b'=') {
+        Err(ScopedEnvError::NameContainsEquals { name: name.into() })
+    } else if bytes.contains(&b'\0') {
+        Err(ScopedEnvError::NameContainsNul { name: name.into() })
+    } else {
+        Ok(())
+    }
+}
+
+pub(crate) fn validate_value(name: &OsStr, value: &OsStr) -> Result<(), ScopedEnvError> {
+    if value.as_encoded_bytes().contains(&b'\0') {
+        Err(ScopedEnvError::ValueContainsNul {
+            name: name.into(),
+            value: value.into(),
+        })
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,360 @@
+use std::env;
+use std::error::Error;
+use std::ffi::OsString;
+use std::fmt;
+
+use crate::{lock, ScopedEnvSetBuilder};
+
+/// The reason the values of a [`ScopedEnvSetBuilder`] could not be
+/// interpolated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InterpolationError {
+    /// The value of the variable {name} contains a malformed `${...}`
+    /// reference.
+    Syntax { name: OsString, message: String },
+    /// The variable {name}, referenced as `${name:?message}` by the
+    /// value of {referenced_by}, was unset or empty.
+    Required {
+        name: OsString,
+        referenced_by: OsString,
+        message: String,
+    },
+}
+
+impl fmt::Display for InterpolationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InterpolationError::Syntax { name, message } => write!(
+                f,
+                "invalid interpolation in the value of environment variable {:?}: {}",
+                name, message
+            ),
+            InterpolationError::Required {
+                name,
+                referenced_by,
+                message,
+            } if message.is_empty() => write!(
+                f,
+                "environment variable {:?} needed by {:?} is not set or empty",
+                name, referenced_by
+            ),
+            InterpolationError::Required {
+                name,
+                referenced_by,
+                message,
+            } => write!(
+                f,
+                "environment variable {:?} needed by {:?}: {}",
+                name, referenced_by, message
+            ),
+        }
+    }
+}
+
+impl Error for InterpolationError {}
+
+impl ScopedEnvSetBuilder {
+    /// Expands `${VAR}`, `${VAR:-default}` and `${VAR:?message}` in
+    /// every value. These follow the shell: a variable that is not
+    /// set expands to nothing, `:-` uses the default when the
+    /// variable is unset or empty, and `:?` fails with the message
+    /// in that case. Defaults can hold further references, `$$` is
+    /// a literal `$`, and values that are not valid unicode are left
+    /// as they are.
+    ///
+    /// A reference takes the value of the closest earlier entry for
+    /// that variable, already expanded, and otherwise the value in
+    /// the environment of the current process. Later entries are
+    /// never seen, so `PATH=/opt/bin:${PATH}` extends the previous
+    /// value and values cannot refer to each other in a cycle.
+    ///
+    /// ```rust
+    /// use scoped_env::ScopedEnvSet;
+    /// let _c = ScopedEnvSet::builder()
+    ///     .set("HOST", "localhost")
+    ///     .set("URL", "http://${HOST}:${HOST_PORT:-8080}/api")
+    ///     .interpolate()
+    ///     .unwrap()
+    ///     .apply();
+    /// assert_eq!(std::env::var("URL").unwrap().as_str(), "http://localhost:8080/api");
+    /// ```
+    ///
+    /// Every `$` in the values is taken as interpolation syntax, so
+    /// this does not suit lists loaded with
+    /// [`ScopedEnvSetBuilder::from_dotenv`], whose single quoted
+    /// values and `\$` escapes are meant literally. Load those with
+    /// [`ScopedEnvSetBuilder::from_dotenv_interpolated`] instead.
+    pub fn interpolate(self) -> Result<Self, InterpolationError> {
+        Ok(interpolate(self.as_ref())?.into_iter().collect())
+    }
+}
+
+/// Expands every value in {vars}, where a literal `$` is written
+/// `$$`. See [`ScopedEnvSetBuilder::interpolate`].
+pub(crate) fn interpolate(
+    vars: &[(OsString, Option<OsString>)],
+) -> Result<Vec<(OsString, Option<OsString>)>, InterpolationError> {
+    let _lock = lock();
+    let mut resolver = Resolver {
+        vars,
+        expanded: Vec::with_capacity(vars.len()),
+    };
+
+    for (index, (_, value)) in vars.iter().enumerate() {
+        let value = match value {
+            None => None,
+            Some(value) => match value.to_str() {
+                None => Some(value.clone()),
+                Some(text) => Some(resolver.expand(index, text)?),
+            },
+        };
+        resolver.expanded.push(value);
+    }
+
+    Ok(vars
+        .iter()
+        .map(|(name, _)| name.clone())
+        .zip(resolver.expanded)
+        .collect())
+}
+
+struct Resolver<'a> {
+    vars: &'a [(OsString, Option<OsString>)],
+    /// The expanded values of the entries before the current one.
+    expanded: Vec<Option<OsString>>,
+}
+
+impl Resolver<'_> {
+    /// The value of the variable {name} as referenced from entry
+    /// {index}.
+    fn lookup(&self, index: usize, name: &str) -> Option<OsString> {
+        match (0..index).rev().find(|&i| self.vars[i].0 == name) {
+            Some(i) => self.expanded[i].clone(),
+            None => env::var_os(name),
+        }
+    }
+
+    /// Expands every reference in {text}, which belongs to entry
+    /// {index}.
+    fn expand(&self, index: usize, text: &str) -> Result<OsString, InterpolationError> {
+        let mut expanded = OsString::new();
+        let mut rest = text;
+        while let Some(dollar) = rest.find('$') {
+            expanded.push(&rest[..dollar]);
+            rest = &rest[dollar..];
+
+            if let Some(after) = rest.strip_prefix("$$") {
+                expanded.push("$");
+                rest = after;
+            } else if let Some(after) = rest.strip_prefix("${") {
+                let end = self.closing_brace(index, after)?;
+                self.reference(index, &after[..end], &mut expanded)?;
+                rest = &after[end + 1..];
+            } else {
+                expanded.push("$");
+                rest = &rest[1..];
+            }
+        }
+
+        expanded.push(rest);
+        Ok(expanded)
+    }
+
+    /// The position of the `}` that closes a reference whose body
+    /// starts at the beginning of {text}.
+    fn closing_brace(&self, index: usize, text: &str) -> Result<usize, InterpolationError> {
+        let bytes = text.as_bytes();
+        let mut depth = 0;
+        let mut i = 0;
+        while i < bytes.len() {
+            match bytes[i] {
+                b'$' if bytes.get(i + 1) == Some(&b'{') => {
+                    depth += 1;
+                    i += 1;
+                }
+                b'}' if depth == 0 => return Ok(i),
+                b'}' => depth -= 1,
+                _ => {}
+            }
+            i += 1;
+        }
+
+        Err(self.syntax_error(index, "unterminated \"${\""))
+    }
+
+    /// Expands the body of a single `${...}` reference onto
+    /// {expanded}.
+    fn reference(
+        &self,
+        index: usize,
+        body: &str,
+        expanded: &mut OsString,
+    ) -> Result<(), InterpolationError> {
+        let (name, operator) = match body.find(':') {
+            Some(colon) => (&body[..colon], Some(&body[colon + 1..])),
+            None => (body, None),
+        };
+        if name.is_empty() {
+            return Err(self.syntax_error(index, "empty variable name in \"${}\""));
+        }
+        if let Some(operator) = operator.filter(|op| !op.starts_with(['-', '?'])) {
+            let message = format!("unknown operator \":{}\" in \"${{{}}}\"", operator, body);
+            return Err(self.syntax_error(index, message));
+        }
+
+        let value = self.lookup(index, name);
+        match (value, operator) {
+            (Some(value), None) => expanded.push(value),
+            (Some(value), Some(_)) if !value.is_empty() => expanded.push(value),
+            (_, None) => {}
+            (_, Some(operator)) => match operator.strip_prefix('-') {
+                Some(default) => expanded.push(self.expand(index, default)?),
+                None => {
+                    return Err(InterpolationError::Required {
+                        name: name.into(),
+                        referenced_by: self.vars[index].0.clone(),
+                        message: operator[1..].into(),
+                    })
+                }
+            },
+        }
+
+        Ok(())
+    }
+
+    fn syntax_error(&self, index: usize, message: impl Into<String>) -> InterpolationError {
+        InterpolationError::Syntax {
+            name: self.vars[index].0.clone(),
+            message: message.into(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ScopedEnv;
+
+    fn expand(
+        vars: &[(&str, Option<&str>)],
+    ) -> Result<Vec<(String, Option<String>)>, InterpolationError> {
+        let builder: ScopedEnvSetBuilder = vars.iter().copied().collect();
+        Ok(builder
+            .interpolate()?
+            .as_ref()
+            .iter()
+            .map(|(name, value)| {
+                let value = value
+                    .as_ref()
+                    .map(|value| value.to_str().unwrap().to_owned());
+                (name.to_str().unwrap().to_owned(), value)
+            })
+            .collect())
+    }
+
+    fn values(vars: &[(&str, Option<&str>)]) -> Vec<Option<String>> {
+        expand(vars)
+            .unwrap()
+            .into_iter()
+            .map(|(_, value)| value)
+            .collect()
+    }
+
+    #[test]
+    fn does_expand_references_and_defaults() {
+        let _c = ScopedEnv::set("INTERPOLATE_HOST", "example.com");
+        let _d = ScopedEnv::set("INTERPOLATE_EMPTY", "");
+        assert_eq!(
+            values(&[
+                (
+                    "URL",
+                    Some("http://${INTERPOLATE_HOST}:${INTERPOLATE_PORT:-8080}/api")
+                ),
+                (
+                    "EMPTY",
+                    Some("[${INTERPOLATE_EMPTY:-default}][${INTERPOLATE_EMPTY}]")
+                ),
+                ("NESTED", Some("${INTERPOLATE_UNSET:-${INTERPOLATE_HOST}}")),
+                ("LITERAL", Some("$$HOME costs $5")),
+            ]),
+            vec![
+                Some("http://example.com:8080/api".into()),
+                Some("[default][]".into()),
+                Some("example.com".into()),
+                Some("$HOME costs $5".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn does_prefer_entries_over_the_environment() {
+        let _c = ScopedEnv::set("INTERPOLATE_PATH", "/usr/bin");
+        assert_eq!(
+            values(&[
+                ("INTERPOLATE_PATH", Some("/opt/bin:${INTERPOLATE_PATH}")),
+                ("INTERPOLATE_PATH", Some("/home/bin:${INTERPOLATE_PATH}")),
+                ("INTERPOLATE_GONE", None),
+                ("REMOVED", Some("${INTERPOLATE_GONE:-fallback}")),
+            ]),
+            vec![
+                Some("/opt/bin:/usr/bin".into()),
+                Some("/home/bin:/opt/bin:/usr/bin".into()),
+                None,
+                Some("fallback".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn does_not_see_later_entries() {
+        let _lock = lock();
+        env::remove_var("INTERPOLATE_A");
+        env::remove_var("INTERPOLATE_B");
+        assert_eq!(
+            values(&[
+                ("INTERPOLATE_A", Some("${INTERPOLATE_B:-none}")),
+                ("INTERPOLATE_B", Some("${INTERPOLATE_A}")),
+            ]),
+            vec![Some("none".into()), Some("none".into())]
+        );
+    }
+
+    #[test]
+    fn does_report_required_variables() {
+        let err =
+            expand(&[("URL", Some("http://${INTERPOLATE_MISSING:?set the host}"))]).unwrap_err();
+        assert_eq!(
+            err,
+            InterpolationError::Required {
+                name: "INTERPOLATE_MISSING".into(),
+                referenced_by: "URL".into(),
+                message: "set the host".into(),
+            }
+        );
+        assert_eq!(
+            err.to_string(),
+            r#"environment variable "INTERPOLATE_MISSING" needed by "URL": set the host"#
+        );
+
+        let err = expand(&[("URL", Some("${INTERPOLATE_MISSING:?}"))]).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            r#"environment variable "INTERPOLATE_MISSING" needed by "URL" is not set or empty"#
+        );
+    }
+
+    #[test]
+    fn does_report_syntax_errors() {
+        let message = |value| match expand(&[("URL", Some(value))]) {
+            Err(InterpolationError::Syntax { message, .. }) => message,
+            other => panic!("expected a syntax error, got {:?}", other),
+        };
+
+        assert_eq!(message("http://${HOST"), r#"unterminated "${""#);
+        assert_eq!(message("${}"), r#"empty variable name in "${}""#);
+        assert_eq!(
+            message("${HOST:+x}"),
+            r#"unknown operator ":+x" in "${HOST:+x}""#
+        );
+    }
+}
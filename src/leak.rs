@@ -0,0 +1,135 @@
+use std::thread;
+
+use crate::{lock, EnvDiff, EnvLock, EnvSnapshot};
+
+/// Fails a test that leaves the environment different from how it
+/// found it. An instance records the whole environment when it is
+/// created and compares it again when it goes out of scope; any
+/// variable that was added, changed or removed and not put back,
+/// for example by code that called `std::env::set_var` directly,
+/// causes a panic naming the offending variables. The environment
+/// is restored before panicking so the leak does not reach later
+/// tests.
+///
+/// Like `ScopedEnv`, every instance holds the process-wide
+/// environment lock until it is dropped, so guards on other threads
+/// cannot be mistaken for leaks.
+///
+/// ```rust,should_panic
+/// use scoped_env::LeakGuard;
+/// let _c = LeakGuard::new();
+/// std::env::set_var("LEAKED", "oops");
+/// ```
+pub struct LeakGuard {
+    before: EnvSnapshot,
+    _lock: EnvLock,
+}
+
+impl LeakGuard {
+    /// Records the current environment. The returned instance
+    /// should be assigned to a `_name` binding at the top of the
+    /// test so that it lasts for the whole test.
+    pub fn new() -> Self {
+        let _lock = lock();
+        Self {
+            before: EnvSnapshot::capture(),
+            _lock,
+        }
+    }
+
+    /// Compares the environment with the one recorded when this
+    /// instance was created, without restoring anything.
+    pub fn check(&self) -> Result<(), EnvDiff> {
+        // The lock is already held through `_lock`, maybe by another
+        // thread if this instance has moved, so taking it again could
+        // wait forever.
+        let diff = self.before.diff(&EnvSnapshot::capture_locked());
+        if diff.is_empty() {
+            Ok(())
+        } else {
+            Err(diff)
+        }
+    }
+}
+
+impl Default for LeakGuard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for LeakGuard {
+    fn drop(&mut self) {
+        if let Err(diff) = self.check() {
+            self.before.restore_locked();
+
+            let names: Vec<_> = diff
+                .names()
+                .map(|name| name.to_string_lossy().into_owned())
+                .collect();
+            let message = format!(
+                "environment variables leaked: {}\n{}",
+                names.join(", "),
+                diff
+            );
+            if thread::panicking() {
+                eprintln!("warning: {}", message);
+            } else {
+                panic!("{}", message);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ScopedEnv;
+    use std::env;
+    use std::panic;
+
+    #[test]
+    fn does_allow_scoped_changes() {
+        let guard = LeakGuard::new();
+        {
+            let _c = ScopedEnv::set("LEAK_A", "hello");
+            assert!(guard.check().is_err());
+        }
+
+        assert!(guard.check().is_ok());
+    }
+
+    #[test]
+    fn does_panic_on_leak() {
+        let _lock = lock();
+        env::set_var("LEAK_B", "OLD_VALUE");
+        let result = panic::catch_unwind(|| {
+            let _c = LeakGuard::new();
+            env::set_var("LEAK_B", "changed");
+            env::set_var("LEAK_C", "added");
+        });
+
+        let payload = result.unwrap_err();
+        let message = payload.downcast_ref::<String>().unwrap();
+        assert!(message.starts_with("environment variables leaked: LEAK_B, LEAK_C\n"));
+        assert_eq!(env::var("LEAK_B").unwrap(), "OLD_VALUE");
+        assert_eq!(env::var_os("LEAK_C"), None);
+        env::remove_var("LEAK_B");
+    }
+
+    #[test]
+    fn does_check_when_dropped_on_another_thread() {
+        let _lock = lock();
+        let guard = LeakGuard::new();
+        std::thread::spawn(move || drop(guard)).join().unwrap();
+
+        let guard = LeakGuard::new();
+        env::set_var("LEAK_D", "added");
+        let result = std::thread::spawn(move || drop(guard)).join();
+
+        let payload = result.unwrap_err();
+        let message = payload.downcast_ref::<String>().unwrap();
+        assert!(message.starts_with("environment variables leaked: LEAK_D\n"));
+        assert_eq!(env::var_os("LEAK_D"), None);
+    }
+}
@@ -1,15 +1,72 @@
 use std::env;
 use std::ffi::{OsStr, OsString};
 
+#[cfg(test)]
+extern crate self as scoped_env;
+
+mod command;
+#[cfg(feature = "serde")]
+mod de;
+mod dotenv;
+mod error;
+mod interpolate;
+mod leak;
+mod lock;
+mod macros;
+pub mod overlay;
+mod path;
+mod provider;
+mod recording;
+mod remove;
+mod scope;
+mod set;
+mod snapshot;
+mod tamper;
+pub mod thread;
+mod value;
+
+pub use command::CommandEnvExt;
+#[cfg(feature = "serde")]
+pub use de::{from_env, from_snapshot, FromEnvError, FromEnvOptions};
+pub use dotenv::DotenvError;
+pub use error::{ParseVarError, ScopedEnvError};
+pub use interpolate::InterpolationError;
+pub use leak::LeakGuard;
+pub use lock::{lock, EnvLock};
+#[doc(hidden)]
+pub use macros::__private;
+pub use provider::{EnvProvider, LayeredEnv, MapEnv, OverlayEnv, ProcessEnv};
+pub use recording::{EnvRead, RecordingEnv};
+pub use scope::with_env;
+#[cfg(feature = "tokio")]
+pub use scope::with_env_async;
+#[cfg(feature = "macros")]
+pub use scoped_env_macros::{isolated, scoped_env, ScopedEnv};
+pub use set::{ScopedEnvSet, ScopedEnvSetBuilder};
+pub use snapshot::{EnvDiff, EnvSnapshot, ScopedEnvSnapshot};
+pub use tamper::{set_tamper_policy, tamper_policy, TamperPolicy};
+pub use value::var_as;
+
 /// A rust lifetime scope for a set environment
 /// variable. When an instance goes out of scope it will
-/// automatically cleanup the environment
+/// automatically cleanup the environment.
+///
+/// Every instance holds the process-wide environment lock
+/// (see [`lock`]) until it is dropped, so guards created on
+/// different threads never interleave.
+///
+/// If the variable no longer holds the value the instance set
+/// when it goes out of scope, the [`TamperPolicy`] decides
+/// whether that is reported.
 pub struct ScopedEnv<T>
 where
     T: AsRef<OsStr>,
 {
     name: T,
     old_value: Option<OsString>,
+    new_value: Option<OsString>,
+    policy: Option<TamperPolicy>,
+    _lock: EnvLock,
 }
 
 impl<T> ScopedEnv<T>
@@ -19,17 +76,57 @@ where
     /// Sets the environment variable {name} to {value}. The
     /// returned instance should be assigned to a `_name`
     /// binding so that it lasts as long as the current
-    /// block.
+    /// block. The name and value do not need to be the same
+    /// type.
     ///
     /// ```rust
     /// use scoped_env::ScopedEnv;
     /// let c = ScopedEnv::set("HELLO", "WORLD");
     /// assert_eq!(std::env::var(c).unwrap().as_str(), "WORLD");
+    ///
+    /// let c = ScopedEnv::set(String::from("HELLO"), "WORLD");
+    /// assert_eq!(std::env::var(c).unwrap().as_str(), "WORLD");
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if {name} is empty, contains an ASCII equals sign
+    /// `=` or a NUL character, or if {value} contains a NUL
+    /// character. Use [`ScopedEnv::try_set`] to handle these as
+    /// errors instead.
+    pub fn set<V>(name: T, value: V) -> Self
+    where
+        V: AsRef<OsStr>,
+    {
+        Self::try_set(name, value).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Sets the environment variable {name} to {value}, returning
+    /// an error instead of panicking if either is not valid for
+    /// the environment.
+    ///
+    /// ```rust
+    /// use scoped_env::{ScopedEnv, ScopedEnvError};
+    /// let err = ScopedEnv::try_set("HELLO=", "WORLD").err().unwrap();
+    /// assert_eq!(err, ScopedEnvError::NameContainsEquals { name: "HELLO=".into() });
     /// ```
-    pub fn set(name: T, value: T) -> Self {
+    pub fn try_set<V>(name: T, value: V) -> Result<Self, ScopedEnvError>
+    where
+        V: AsRef<OsStr>,
+    {
+        error::validate_name(name.as_ref())?;
+        error::validate_value(name.as_ref(), value.as_ref())?;
+
+        let _lock = lock();
         let old_value = env::var_os(name.as_ref());
-        env::set_var(name.as_ref(), value);
-        Self { name, old_value }
+        env::set_var(name.as_ref(), value.as_ref());
+        Ok(Self {
+            name,
+            old_value,
+            new_value: Some(value.as_ref().to_os_string()),
+            policy: None,
+            _lock,
+        })
     }
 
     /// Removes the environment variable {name} from the
@@ -40,6 +137,7 @@ where
     ///
     /// ```rust
     /// use scoped_env::ScopedEnv;
+    /// let _lock = scoped_env::lock();
     /// std::env::set_var("HELLO", "WORLD");
     /// {
     ///     let c = ScopedEnv::remove("HELLO");
@@ -47,10 +145,52 @@ where
     /// }
     /// assert_eq!(std::env::var("HELLO").unwrap().as_str(), "WORLD");
     /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics if {name} is empty or contains an ASCII equals
+    /// sign `=` or a NUL character. Use [`ScopedEnv::try_remove`]
+    /// to handle these as errors instead.
     pub fn remove(name: T) -> Self {
+        Self::try_remove(name).unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Removes the environment variable {name}, returning an
+    /// error instead of panicking if the name is not valid for
+    /// the environment.
+    ///
+    /// ```rust
+    /// use scoped_env::{ScopedEnv, ScopedEnvError};
+    /// let err = ScopedEnv::try_remove("").err().unwrap();
+    /// assert_eq!(err, ScopedEnvError::EmptyName);
+    /// ```
+    pub fn try_remove(name: T) -> Result<Self, ScopedEnvError> {
+        error::validate_name(name.as_ref())?;
+
+        let _lock = lock();
         let old_value = env::var_os(name.as_ref());
         env::remove_var(name.as_ref());
-        Self { name, old_value }
+        Ok(Self {
+            name,
+            old_value,
+            new_value: None,
+            policy: None,
+            _lock,
+        })
+    }
+
+    /// Uses {policy} instead of the default [`TamperPolicy`] if the
+    /// variable was changed by someone else while this instance
+    /// was in scope.
+    ///
+    /// ```rust,should_panic
+    /// use scoped_env::{ScopedEnv, TamperPolicy};
+    /// let _c = ScopedEnv::set("HELLO", "WORLD").on_tamper(TamperPolicy::Panic);
+    /// std::env::set_var("HELLO", "THERE");
+    /// ```
+    pub fn on_tamper(mut self, policy: TamperPolicy) -> Self {
+        self.policy = Some(policy);
+        self
     }
 }
 
@@ -68,13 +208,30 @@ where
     T: AsRef<OsStr>,
 {
     fn drop(&mut self) {
-        match self.old_value {
-            Some(ref old_value) => env::set_var(self.as_ref(), old_value),
-            None => env::remove_var(self),
+        let found = env::var_os(self.name.as_ref());
+        let tampered = tamper::check(
+            self.name.as_ref(),
+            self.new_value.as_deref(),
+            found.as_deref(),
+            self.policy,
+        );
+        restore_var(self.name.as_ref(), self.old_value.as_deref());
+
+        if let Some(tampered) = tampered {
+            tampered.report();
         }
     }
 }
 
+/// Puts the environment variable {name} back to {old_value},
+/// removing it if there was no previous value.
+pub(crate) fn restore_var(name: &OsStr, old_value: Option<&OsStr>) {
+    match old_value {
+        Some(old_value) => env::set_var(name, old_value),
+        None => env::remove_var(name),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -85,8 +242,18 @@ mod tests {
         assert_eq!(env::var(c).unwrap(), "hello");
     }
 
+    #[test]
+    fn does_set_with_different_types() {
+        let c = ScopedEnv::set(String::from("FOOBAR3"), "hello");
+        assert_eq!(env::var(&c).unwrap(), "hello");
+
+        let c = ScopedEnv::set("FOOBAR3", OsString::from("world"));
+        assert_eq!(env::var(&c).unwrap(), "world");
+    }
+
     #[test]
     fn does_unset_at_end_of_block() {
+        let _lock = lock();
         env::remove_var("FOOBAR1");
         {
             let c = ScopedEnv::set("FOOBAR1", "hello");
@@ -98,6 +265,7 @@ mod tests {
 
     #[test]
     fn does_reset_at_end_of_block() {
+        let _lock = lock();
         env::set_var("FOOBAR1", "OLD_VALUE");
         {
             let c = ScopedEnv::set("FOOBAR1", "hello");
@@ -109,6 +277,7 @@ mod tests {
 
     #[test]
     fn does_remove() {
+        let _lock = lock();
         env::set_var("FOOBAR", "SOME_VALUE");
         {
             let c = ScopedEnv::remove("FOOBAR");
@@ -117,4 +286,232 @@ mod tests {
 
         assert_eq!(env::var_os("FOOBAR").unwrap(), "SOME_VALUE");
     }
+
+    #[test]
+    fn try_set_rejects_invalid_names_and_values() {
+        assert_eq!(
+            ScopedEnv::try_set("", "hello").err(),
+            Some(ScopedEnvError::EmptyName)
+        );
+        assert_eq!(
+            ScopedEnv::try_set("FOO\0BAR", "hello").err(),
+            Some(ScopedEnvError::NameContainsNul {
+                name: "FOO\0BAR".into()
+            })
+        );
+        assert_eq!(
+            ScopedEnv::try_set("FOOBAR4", "hel\0lo").err(),
+            Some(ScopedEnvError::ValueContainsNul {
+                name: "FOOBAR4".into(),
+                value: "hel\0lo".into()
+            })
+        );
+        assert_eq!(env::var_os("FOOBAR4"), None);
+    }
+
+    #[test]
+    fn try_remove_rejects_invalid_names() {
+        let err = ScopedEnv::try_remove("FOO=BAR").err().unwrap();
+        assert_eq!(err.name(), Some(OsStr::new("FOO=BAR")));
+        assert_eq!(
+            err.to_string(),
+            "environment variable name \"FOO=BAR\" contains an equals sign"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "environment variable name is empty")]
+    fn set_panics_with_error_message() {
+        ScopedEnv::set("", "hello");
+    }
+
+    #[test]
+    fn holds_lock_for_lifetime() {
+        use std::sync::mpsc;
+        use std::thread;
+        use std::time::Duration;
+
+        let c = ScopedEnv::set("FOOBAR2", "hello");
+        let (tx, rx) = mpsc::channel();
+        let handle = thread::spawn(move || {
+            let _c = ScopedEnv::set("FOOBAR2", "other");
+            tx.send(()).unwrap();
+        });
+
+        assert!(rx.recv_timeout(Duration::from_millis(50)).is_err());
+        assert_eq!(env::var("FOOBAR2").unwrap(), "hello");
+        drop(c);
+        rx.recv().unwrap();
+        handle.join().unwrap();
+    }
+
+    #[cfg(feature = "macros")]
+    use crate::scoped_env;
+
+    #[cfg(feature = "macros")]
+    #[scoped_env(FOOBAR5 = "hello", FOOBAR6 = unset, "FOOBAR7" = String::from("world"))]
+    fn with_attribute() -> (Option<OsString>, Option<OsString>, Option<OsString>) {
+        (
+            env::var_os("FOOBAR5"),
+            env::var_os("FOOBAR6"),
+            env::var_os("FOOBAR7"),
+        )
+    }
+
+    #[cfg(feature = "macros")]
+    #[test]
+    fn attribute_applies_and_restores() {
+        let _lock = lock();
+        env::set_var("FOOBAR6", "OLD_VALUE");
+        assert_eq!(
+            with_attribute(),
+            (Some("hello".into()), None, Some("world".into()))
+        );
+        assert_eq!(env::var_os("FOOBAR5"), None);
+        assert_eq!(env::var("FOOBAR6").unwrap(), "OLD_VALUE");
+    }
+
+    #[cfg(feature = "macros")]
+    #[test]
+    #[scoped_env(FOOBAR8 = "hello")]
+    fn attribute_supports_result() -> Result<(), env::VarError> {
+        assert_eq!(env::var("FOOBAR8")?, "hello");
+        Ok(())
+    }
+
+    #[cfg(feature = "macros")]
+    #[scoped_env()]
+    fn with_empty_attribute() -> Option<u64> {
+        lock::held_owner()
+    }
+
+    #[cfg(feature = "macros")]
+    #[scoped_env]
+    fn with_bare_attribute() -> Option<u64> {
+        lock::held_owner()
+    }
+
+    #[cfg(feature = "macros")]
+    #[test]
+    fn attribute_without_entries_takes_the_lock() {
+        assert!(lock::held_owner().is_none());
+        assert!(with_empty_attribute().is_some());
+        assert!(with_bare_attribute().is_some());
+    }
+
+    #[cfg(feature = "macros")]
+    #[crate::isolated]
+    fn isolated_leak() {
+        let _c = ScopedEnv::set("FOOBAR13", "scoped");
+        env::set_var("FOOBAR14", "leaked");
+    }
+
+    #[cfg(feature = "macros")]
+    #[test]
+    fn isolated_panics_on_leak() {
+        let _lock = lock();
+        let payload = std::panic::catch_unwind(isolated_leak).unwrap_err();
+        let message = payload.downcast_ref::<String>().unwrap();
+        assert!(message.starts_with("environment variables leaked: FOOBAR14\n"));
+        assert_eq!(env::var_os("FOOBAR13"), None);
+        assert_eq!(env::var_os("FOOBAR14"), None);
+    }
+
+    #[cfg(all(feature = "macros", feature = "tokio"))]
+    #[crate::isolated]
+    async fn isolated_async(value: &'static str) -> String {
+        let _c = ScopedEnv::set("FOOBAR15", value);
+        tokio::task::yield_now().await;
+        env::var("FOOBAR15").unwrap()
+    }
+
+    #[cfg(all(feature = "macros", feature = "tokio"))]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn isolated_supports_async() {
+        let tasks: Vec<_> = ["a", "b", "c", "d"]
+            .iter()
+            .map(|&value| tokio::spawn(isolated_async(value)))
+            .collect();
+        for (task, value) in tasks.into_iter().zip(["a", "b", "c", "d"]) {
+            assert_eq!(task.await.unwrap(), value);
+        }
+    }
+
+    #[cfg(all(feature = "macros", feature = "tokio"))]
+    #[scoped_env(FOOBAR9 = "hello")]
+    #[tokio::test]
+    async fn attribute_supports_async() -> Result<(), env::VarError> {
+        tokio::task::yield_now().await;
+        assert_eq!(env::var("FOOBAR9")?, "hello");
+        Ok(())
+    }
+
+    #[cfg(feature = "macros")]
+    #[derive(crate::ScopedEnv)]
+    #[env(prefix = "DERIVED_")]
+    struct DerivedEnv {
+        #[env(name = "FOOBAR12")]
+        url: String,
+        port: u16,
+        user: Option<&'static str>,
+        #[env(unset_if_none)]
+        r#token: Option<String>,
+    }
+
+    #[cfg(feature = "macros")]
+    #[test]
+    fn derive_applies_and_restores() {
+        let _lock = lock();
+        env::set_var("DERIVED_USER", "OLD_USER");
+        env::set_var("DERIVED_TOKEN", "OLD_TOKEN");
+        let fixture = DerivedEnv {
+            url: "postgres://localhost".into(),
+            port: 8080,
+            user: None,
+            token: None,
+        };
+        {
+            let _c = fixture.apply();
+            assert_eq!(env::var("FOOBAR12").unwrap(), "postgres://localhost");
+            assert_eq!(env::var("DERIVED_PORT").unwrap(), "8080");
+            assert_eq!(env::var("DERIVED_USER").unwrap(), "OLD_USER");
+            assert_eq!(env::var_os("DERIVED_TOKEN"), None);
+        }
+
+        assert_eq!(env::var_os("FOOBAR12"), None);
+        assert_eq!(env::var_os("DERIVED_PORT"), None);
+        assert_eq!(env::var("DERIVED_TOKEN").unwrap(), "OLD_TOKEN");
+        env::remove_var("DERIVED_USER");
+        env::remove_var("DERIVED_TOKEN");
+    }
+
+    #[test]
+    fn does_restore_after_tampering() {
+        let _lock = lock();
+        env::set_var("FOOBAR10", "OLD_VALUE");
+        {
+            let _c = ScopedEnv::set("FOOBAR10", "hello").on_tamper(TamperPolicy::Warn);
+            env::set_var("FOOBAR10", "tampered");
+        }
+
+        assert_eq!(env::var("FOOBAR10").unwrap(), "OLD_VALUE");
+    }
+
+    #[test]
+    fn does_panic_after_tampering() {
+        let _lock = lock();
+        env::remove_var("FOOBAR11");
+        let result = std::panic::catch_unwind(|| {
+            let _c = ScopedEnv::remove("FOOBAR11").on_tamper(TamperPolicy::Panic);
+            env::set_var("FOOBAR11", "tampered");
+        });
+
+        let payload = result.unwrap_err();
+        assert_eq!(
+            payload.downcast_ref::<String>().unwrap(),
+            "environment variable \"FOOBAR11\" was changed while scoped: \
+             expected unset, found \"tampered\""
+        );
+        assert_eq!(env::var_os("FOOBAR11"), None);
+    }
 }
@@ -0,0 +1,187 @@
+use std::cell::Cell;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Condvar, Mutex, MutexGuard, PoisonError};
+
+struct State {
+    owner: Option<u64>,
+    depth: usize,
+}
+
+static STATE: Mutex<State> = Mutex::new(State {
+    owner: None,
+    depth: 0,
+});
+static RELEASED: Condvar = Condvar::new();
+static NEXT_OWNER: AtomicU64 = AtomicU64::new(1);
+#[cfg(feature = "tokio")]
+static RELEASED_ASYNC: tokio::sync::Notify = tokio::sync::Notify::const_new();
+
+thread_local! {
+    static OWNER: Cell<u64> = const { Cell::new(0) };
+}
+
+fn state() -> MutexGuard<'static, State> {
+    STATE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn new_owner() -> u64 {
+    NEXT_OWNER.fetch_add(1, Ordering::Relaxed)
+}
+
+/// The lock owner identity of the current thread.
+pub(crate) fn current_owner() -> u64 {
+    OWNER.with(|owner| {
+        if owner.get() == 0 {
+            owner.set(new_owner());
+        }
+        owner.get()
+    })
+}
+
+/// The owner identity of the current thread if it holds the lock.
+pub(crate) fn held_owner() -> Option<u64> {
+    let current = current_owner();
+    if state().owner == Some(current) {
+        Some(current)
+    } else {
+        None
+    }
+}
+
+/// Makes the current thread act as {owner} until the returned
+/// value is dropped, so that any lock acquired meanwhile is shared
+/// with {owner}.
+pub(crate) fn enter_owner(owner: u64) -> OwnerGuard {
+    OwnerGuard {
+        previous: OWNER.with(|current| current.replace(owner)),
+    }
+}
+
+pub(crate) struct OwnerGuard {
+    previous: u64,
+}
+
+impl Drop for OwnerGuard {
+    fn drop(&mut self) {
+        OWNER.with(|owner| owner.set(self.previous));
+    }
+}
+
+/// Runs {body} with the current thread acting as {owner}.
+#[cfg(feature = "tokio")]
+pub(crate) fn with_owner<R>(owner: u64, body: impl FnOnce() -> R) -> R {
+    let _owner = enter_owner(owner);
+    body()
+}
+
+/// A handle on the process-wide environment lock. The lock is
+/// released when the last handle held by the owning thread goes
+/// out of scope.
+#[derive(Debug)]
+pub struct EnvLock {
+    _private: (),
+}
+
+/// Acquires the process-wide environment lock, blocking until no
+/// other thread holds it. Every `ScopedEnv` holds this lock for its
+/// whole lifetime, so code that reads the environment while guards
+/// may be active on other threads should hold it as well.
+///
+/// The lock is reentrant: a thread that already holds it (for
+/// example through a `ScopedEnv`) can acquire it again without
+/// blocking.
+///
+/// ```rust
+/// use scoped_env::{lock, ScopedEnv};
+/// let _lock = lock();
+/// let _c = ScopedEnv::set("HELLO", "WORLD");
+/// assert_eq!(std::env::var("HELLO").unwrap().as_str(), "WORLD");
+/// ```
+pub fn lock() -> EnvLock {
+    let owner = current_owner();
+    let mut state = state();
+    while state.owner.is_some_and(|current| current != owner) {
+        state = RELEASED.wait(state).unwrap_or_else(PoisonError::into_inner);
+    }
+
+    state.owner = Some(owner);
+    state.depth += 1;
+    EnvLock { _private: () }
+}
+
+/// Acquires the process-wide environment lock without blocking the
+/// current thread, returning the lock and the owner identity that
+/// holds it. If the current thread already holds the lock then the
+/// lock is shared with it, otherwise a fresh owner is created for
+/// the caller.
+#[cfg(feature = "tokio")]
+pub(crate) async fn lock_async() -> (EnvLock, u64) {
+    let owner = held_owner().unwrap_or_else(new_owner);
+
+    loop {
+        let mut released = std::pin::pin!(RELEASED_ASYNC.notified());
+        released.as_mut().enable();
+
+        let acquired = {
+            let mut state = state();
+            let free = state.owner.is_none_or(|current| current == owner);
+            if free {
+                state.owner = Some(owner);
+                state.depth += 1;
+            }
+            free
+        };
+
+        if acquired {
+            return (EnvLock { _private: () }, owner);
+        }
+        released.await;
+    }
+}
+
+impl Drop for EnvLock {
+    fn drop(&mut self) {
+        let mut state = state();
+        state.depth -= 1;
+        if state.depth == 0 {
+            state.owner = None;
+            RELEASED.notify_all();
+            #[cfg(feature = "tokio")]
+            RELEASED_ASYNC.notify_waiters();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    #[test]
+    fn is_reentrant() {
+        let _outer = lock();
+        let _inner = lock();
+    }
+
+    #[test]
+    fn blocks_other_threads() {
+        let held = lock();
+        let acquired = Arc::new(AtomicBool::new(false));
+        let handle = {
+            let acquired = Arc::clone(&acquired);
+            thread::spawn(move || {
+                let _lock = lock();
+                acquired.store(true, Ordering::SeqCst);
+            })
+        };
+
+        thread::sleep(Duration::from_millis(50));
+        assert!(!acquired.load(Ordering::SeqCst));
+        drop(held);
+        handle.join().unwrap();
+        assert!(acquired.load(Ordering::SeqCst));
+    }
+}
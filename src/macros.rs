@@ -0,0 +1,138 @@
+/// Applies several environment variables at once, returning a
+/// single [`ScopedEnvSet`](crate::ScopedEnvSet) guard over all of
+/// them. Each entry is `"NAME" => value`, where the value is any
+/// expression whose type implements `AsRef<OsStr>`, or
+/// `"NAME" => unset` to remove the variable. It is named after the
+/// guard it returns, leaving `scoped_env` to the attribute macro of
+/// the `macros` feature.
+///
+/// ```rust
+/// use scoped_env::scoped_env_set;
+/// let port = 8080;
+/// let _c = scoped_env_set! {
+///     "HOST" => "localhost",
+///     "HOME" => unset,
+///     "PORT" => format!("{}", port),
+/// };
+/// assert_eq!(std::env::var("PORT").unwrap().as_str(), "8080");
+/// assert!(std::env::var("HOME").is_err());
+/// ```
+///
+/// Naming the same variable twice is a compile time error:
+///
+/// ```rust,compile_fail
+/// use scoped_env::scoped_env_set;
+/// let _c = scoped_env_set! { "HOST" => "localhost", "HOST" => unset };
+/// ```
+#[macro_export]
+macro_rules! scoped_env_set {
+    (@entries [$($entries:expr,)*]) => {{
+        let entries: ::std::vec::Vec<(&str, ::std::option::Option<::std::ffi::OsString>)> =
+            ::std::vec![$($entries),*];
+        entries
+    }};
+    (@entries [$($entries:expr,)*] $name:literal => unset $(, $($rest:tt)*)?) => {
+        $crate::scoped_env_set!(@entries [
+            $($entries,)*
+            ($name, ::std::option::Option::None),
+        ] $($($rest)*)?)
+    };
+    (@entries [$($entries:expr,)*] $name:literal => $value:expr $(, $($rest:tt)*)?) => {
+        $crate::scoped_env_set!(@entries [
+            $($entries,)*
+            ($name, ::std::option::Option::Some($crate::__private::to_os_string(&$value))),
+        ] $($($rest)*)?)
+    };
+    (@names [$($names:literal,)*]) => {
+        const _: () = $crate::__private::assert_unique_names(&[$($names),*]);
+    };
+    (@names [$($names:literal,)*] $name:literal => $value:expr $(, $($rest:tt)*)?) => {
+        $crate::scoped_env_set!(@names [$($names,)* $name,] $($($rest)*)?)
+    };
+    ($($entries:tt)*) => {{
+        $crate::scoped_env_set!(@names [] $($entries)*);
+        $crate::ScopedEnvSet::new($crate::scoped_env_set!(@entries [] $($entries)*))
+    }};
+}
+
+#[doc(hidden)]
+pub mod __private {
+    use std::ffi::{OsStr, OsString};
+
+    pub fn to_os_string<V: AsRef<OsStr> + ?Sized>(value: &V) -> OsString {
+        value.as_ref().to_os_string()
+    }
+
+    pub const fn assert_unique_names(names: &[&str]) {
+        let mut i = 0;
+        while i < names.len() {
+            let mut j = i + 1;
+            while j < names.len() {
+                if str_eq(names[i], names[j]) {
+                    panic!("the same environment variable is named twice in scoped_env_set!");
+                }
+                j += 1;
+            }
+            i += 1;
+        }
+    }
+
+    const fn str_eq(a: &str, b: &str) -> bool {
+        let (a, b) = (a.as_bytes(), b.as_bytes());
+        if a.len() != b.len() {
+            return false;
+        }
+
+        let mut i = 0;
+        while i < a.len() {
+            if a[i] != b[i] {
+                return false;
+            }
+            i += 1;
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lock;
+    use std::env;
+
+    #[test]
+    fn does_apply_and_restore() {
+        let _lock = lock();
+        env::set_var("MACRO_B", "OLD_VALUE");
+        let suffix = 2;
+        {
+            let _c = crate::scoped_env_set! {
+                "MACRO_A" => "1",
+                "MACRO_B" => unset,
+                "MACRO_C" => format!("value-{}", suffix)
+            };
+            assert_eq!(env::var("MACRO_A").unwrap(), "1");
+            assert_eq!(env::var_os("MACRO_B"), None);
+            assert_eq!(env::var("MACRO_C").unwrap(), "value-2");
+        }
+
+        assert_eq!(env::var_os("MACRO_A"), None);
+        assert_eq!(env::var("MACRO_B").unwrap(), "OLD_VALUE");
+        assert_eq!(env::var_os("MACRO_C"), None);
+    }
+
+    #[test]
+    fn does_accept_empty_and_trailing_comma() {
+        let _c = crate::scoped_env_set! {};
+        let _d = crate::scoped_env_set! { "MACRO_D" => "1", };
+        assert_eq!(env::var("MACRO_D").unwrap(), "1");
+    }
+
+    #[test]
+    fn does_compare_names() {
+        super::__private::assert_unique_names(&["A", "AB", "B"]);
+        let duplicate = std::panic::catch_unwind(|| {
+            super::__private::assert_unique_names(&["A", "B", "A"]);
+        });
+        assert!(duplicate.is_err());
+    }
+}
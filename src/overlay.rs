@@ -0,0 +1,224 @@
+//! A thread-local layer of environment variables that sits on top
+//! of the real environment of the process.
+//!
+//! Guards from this module never call `std::env::set_var`; the
+//! values they hold are only visible to the current thread and only
+//! through the functions in this module, which mirror their
+//! `std::env` namesakes. Code that reads its configuration through
+//! them can be tested fully in parallel without taking the
+//! environment lock:
+//!
+//! ```rust
+//! use scoped_env::overlay as env;
+//!
+//! fn log_level() -> String {
+//!     env::var("LOG_LEVEL").unwrap_or_else(|_| "info".into())
+//! }
+//!
+//! let _c = env::set("LOG_LEVEL", "debug");
+//! assert_eq!(log_level(), "debug");
+//! assert!(std::env::var("LOG_LEVEL").is_err());
+//! ```
+//!
+//! Code that reads `std::env` directly will not see the overlay; use
+//! `ScopedEnv` for that.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::env::{self, VarError};
+use std::ffi::{OsStr, OsString};
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::set;
+
+struct Layer {
+    id: u64,
+    vars: BTreeMap<OsString, Option<OsString>>,
+}
+
+static NEXT_LAYER: AtomicU64 = AtomicU64::new(1);
+
+thread_local! {
+    static LAYERS: RefCell<Vec<Layer>> = const { RefCell::new(Vec::new()) };
+}
+
+/// A rust lifetime scope for a layer of overlaid environment
+/// variables. When an instance goes out of scope its layer is
+/// removed again. Instances only affect the thread that created
+/// them and so cannot be sent to another thread.
+pub struct OverlayGuard {
+    id: u64,
+    _not_send: PhantomData<*const ()>,
+}
+
+/// Overlays every `(name, value)` pair in {vars} on the current
+/// thread. A value of `Some` sets the variable and a value of `None`
+/// hides it, even if it is set in the real environment.
+///
+/// ```rust
+/// use scoped_env::overlay;
+/// let _c = overlay::scope(vec![("HELLO", Some("WORLD")), ("PATH", None)]);
+/// assert_eq!(overlay::var("HELLO").unwrap().as_str(), "WORLD");
+/// assert!(overlay::var_os("PATH").is_none());
+/// ```
+pub fn scope<I, K, V>(vars: I) -> OverlayGuard
+where
+    I: IntoIterator<Item = (K, Option<V>)>,
+    K: AsRef<OsStr>,
+    V: AsRef<OsStr>,
+{
+    push(set::os_vars(vars).collect())
+}
+
+/// Overlays the variable {name} with {value} on the current thread.
+pub fn set<K: AsRef<OsStr>, V: AsRef<OsStr>>(name: K, value: V) -> OverlayGuard {
+    scope(Some((name, Some(value))))
+}
+
+/// Hides the variable {name} on the current thread, even if it is
+/// set in the real environment.
+pub fn remove<K: AsRef<OsStr>>(name: K) -> OverlayGuard {
+    scope(Some((name, None::<OsString>)))
+}
+
+/// Every variable overlaid on the current thread, flattened into a
+/// single layer.
+pub(crate) fn current() -> BTreeMap<OsString, Option<OsString>> {
+    LAYERS.with(|layers| {
+        let mut vars = BTreeMap::new();
+        for layer in layers.borrow().iter() {
+            vars.extend(layer.vars.clone());
+        }
+        vars
+    })
+}
+
+pub(crate) fn push(vars: BTreeMap<OsString, Option<OsString>>) -> OverlayGuard {
+    let id = NEXT_LAYER.fetch_add(1, Ordering::Relaxed);
+    LAYERS.with(|layers| layers.borrow_mut().push(Layer { id, vars }));
+    OverlayGuard {
+        id,
+        _not_send: PhantomData,
+    }
+}
+
+impl Drop for OverlayGuard {
+    fn drop(&mut self) {
+        // Layers are usually dropped in reverse order, but a guard
+        // can be dropped early by hand, so look it up by id.
+        LAYERS.with(|layers| layers.borrow_mut().retain(|layer| layer.id != self.id));
+    }
+}
+
+/// Fetches the variable {key}, looking in the overlay of the current
+/// thread before the real environment. Mirrors [`std::env::var_os`].
+pub fn var_os<K: AsRef<OsStr>>(key: K) -> Option<OsString> {
+    let key = key.as_ref();
+    let overlaid = LAYERS.with(|layers| {
+        layers
+            .borrow()
+            .iter()
+            .rev()
+            .find_map(|layer| layer.vars.get(key).cloned())
+    });
+
+    overlaid.unwrap_or_else(|| env::var_os(key))
+}
+
+/// Fetches the variable {key}, looking in the overlay of the current
+/// thread before the real environment. Mirrors [`std::env::var`].
+pub fn var<K: AsRef<OsStr>>(key: K) -> Result<String, VarError> {
+    match var_os(key) {
+        Some(value) => value.into_string().map_err(VarError::NotUnicode),
+        None => Err(VarError::NotPresent),
+    }
+}
+
+/// Every variable in the real environment merged with the overlay of
+/// the current thread, ordered by name. Mirrors
+/// [`std::env::vars_os`].
+pub fn vars_os() -> std::vec::IntoIter<(OsString, OsString)> {
+    let mut vars: BTreeMap<OsString, OsString> = env::vars_os().collect();
+    LAYERS.with(|layers| {
+        for layer in layers.borrow().iter() {
+            for (name, value) in &layer.vars {
+                match value {
+                    Some(value) => vars.insert(name.clone(), value.clone()),
+                    None => vars.remove(name),
+                };
+            }
+        }
+    });
+
+    vars.into_iter().collect::<Vec<_>>().into_iter()
+}
+
+/// Every variable in the real environment merged with the overlay of
+/// the current thread, ordered by name. Mirrors [`std::env::vars`].
+///
+/// # Panics
+///
+/// Panics if any name or value is not valid unicode.
+pub fn vars() -> std::vec::IntoIter<(String, String)> {
+    vars_os()
+        .map(|(name, value)| {
+            let name = name
+                .into_string()
+                .unwrap_or_else(|name| panic!("variable name {:?} is not valid unicode", name));
+            let value = value
+                .into_string()
+                .unwrap_or_else(|value| panic!("value {:?} is not valid unicode", value));
+            (name, value)
+        })
+        .collect::<Vec<_>>()
+        .into_iter()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::thread;
+
+    #[test]
+    fn does_overlay_without_touching_the_environment() {
+        let _c = set("OVERLAY_A", "hello");
+        assert_eq!(var("OVERLAY_A").unwrap(), "hello");
+        assert_eq!(env::var_os("OVERLAY_A"), None);
+        assert!(vars().any(|(name, value)| name == "OVERLAY_A" && value == "hello"));
+    }
+
+    #[test]
+    fn does_layer_and_unwind() {
+        let _c = set("OVERLAY_B", "outer");
+        {
+            let _d = remove("OVERLAY_B");
+            assert_eq!(var_os("OVERLAY_B"), None);
+            assert!(!vars_os().any(|(name, _)| name == "OVERLAY_B"));
+            {
+                let _e = scope(vec![("OVERLAY_B", Some("inner"))]);
+                assert_eq!(var("OVERLAY_B").unwrap(), "inner");
+            }
+            assert_eq!(var_os("OVERLAY_B"), None);
+        }
+
+        assert_eq!(var("OVERLAY_B").unwrap(), "outer");
+    }
+
+    #[test]
+    fn does_stay_on_the_current_thread() {
+        let _c = set("OVERLAY_C", "hello");
+        thread::spawn(|| assert_eq!(var_os("OVERLAY_C"), None))
+            .join()
+            .unwrap();
+    }
+
+    #[test]
+    fn does_remove_layers_dropped_out_of_order() {
+        let c = set("OVERLAY_D", "first");
+        let _d = set("OVERLAY_E", "second");
+        drop(c);
+        assert_eq!(var_os("OVERLAY_D"), None);
+        assert_eq!(var("OVERLAY_E").unwrap(), "second");
+    }
+}
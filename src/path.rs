@@ -0,0 +1,183 @@
+use std::env;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+use crate::error::{self, ScopedEnvError};
+use crate::{lock, ScopedEnv};
+
+const SEPARATOR: u8 = if cfg!(windows) { b';' } else { b':' };
+
+impl<T> ScopedEnv<T>
+where
+    T: AsRef<OsStr>,
+{
+    /// Adds {entry} to the front of the path list held in the
+    /// environment variable {name}, such as `PATH`. The list is
+    /// split and joined with the platform's separator, and the
+    /// exact previous value is restored when the returned
+    /// instance goes out of scope.
+    ///
+    /// ```rust
+    /// use scoped_env::ScopedEnv;
+    /// use std::path::Path;
+    /// let _lock = scoped_env::lock();
+    /// std::env::set_var("MY_PATH", "/usr/bin");
+    /// {
+    ///     let c = ScopedEnv::prepend_path("MY_PATH", "/opt/fake/bin").unwrap();
+    ///     let value = std::env::var_os(c).unwrap();
+    ///     let entries: Vec<_> = std::env::split_paths(&value).collect();
+    ///     assert_eq!(entries, [Path::new("/opt/fake/bin"), Path::new("/usr/bin")]);
+    /// }
+    /// assert_eq!(std::env::var("MY_PATH").unwrap().as_str(), "/usr/bin");
+    /// ```
+    pub fn prepend_path<P: AsRef<Path>>(name: T, entry: P) -> Result<Self, ScopedEnvError> {
+        Self::edit_path(name, |entries| {
+            entries.insert(0, entry.as_ref().to_path_buf());
+        })
+    }
+
+    /// Adds {entry} to the end of the path list held in the
+    /// environment variable {name}. See [`ScopedEnv::prepend_path`].
+    pub fn append_path<P: AsRef<Path>>(name: T, entry: P) -> Result<Self, ScopedEnvError> {
+        Self::edit_path(name, |entries| {
+            entries.push(entry.as_ref().to_path_buf());
+        })
+    }
+
+    /// Removes every occurrence of {entry} from the path list held
+    /// in the environment variable {name}. If the variable is not
+    /// set it is left untouched. See [`ScopedEnv::prepend_path`].
+    pub fn remove_path_entry<P: AsRef<Path>>(name: T, entry: P) -> Result<Self, ScopedEnvError> {
+        Self::edit_path(name, |entries| {
+            entries.retain(|existing| existing != entry.as_ref());
+        })
+    }
+
+    fn edit_path<F>(name: T, edit: F) -> Result<Self, ScopedEnvError>
+    where
+        F: FnOnce(&mut Vec<PathBuf>),
+    {
+        error::validate_name(name.as_ref())?;
+
+        let _lock = lock();
+        let old_value = env::var_os(name.as_ref());
+        // An empty value has no entries, even though splitting it
+        // gives a single empty one, which would put the current
+        // directory on the list.
+        let mut entries: Vec<PathBuf> = match old_value {
+            Some(ref old_value) if !old_value.is_empty() => env::split_paths(old_value).collect(),
+            _ => Vec::new(),
+        };
+        edit(&mut entries);
+
+        if old_value.is_none() && entries.is_empty() {
+            return Ok(Self {
+                name,
+                old_value,
+                new_value: None,
+                policy: None,
+                _lock,
+            });
+        }
+
+        let value = env::join_paths(&entries).map_err(|_| {
+            let entry = entries
+                .iter()
+                .find(|entry| entry.as_os_str().as_encoded_bytes().contains(&SEPARATOR))
+                .cloned()
+                .unwrap_or_default();
+            ScopedEnvError::PathEntryContainsSeparator {
+                name: name.as_ref().into(),
+                entry,
+            }
+        })?;
+        Self::try_set(name, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(unix)]
+    #[test]
+    fn does_prepend_and_append() {
+        let _lock = lock();
+        env::set_var("PATH_A", "/usr/bin:/bin");
+        {
+            let _c = ScopedEnv::prepend_path("PATH_A", "/first").unwrap();
+            let _d = ScopedEnv::append_path("PATH_A", "/last").unwrap();
+            assert_eq!(env::var("PATH_A").unwrap(), "/first:/usr/bin:/bin:/last");
+        }
+
+        assert_eq!(env::var("PATH_A").unwrap(), "/usr/bin:/bin");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn does_not_add_an_empty_entry() {
+        let _lock = lock();
+        env::set_var("PATH_F", "");
+        {
+            let _c = ScopedEnv::prepend_path("PATH_F", "/first").unwrap();
+            assert_eq!(env::var("PATH_F").unwrap(), "/first");
+            {
+                let _d = ScopedEnv::remove_path_entry("PATH_F", "/first").unwrap();
+                assert_eq!(env::var("PATH_F").unwrap(), "");
+            }
+        }
+
+        assert_eq!(env::var_os("PATH_F").unwrap(), "");
+        env::remove_var("PATH_F");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn does_remove_entries() {
+        let _lock = lock();
+        env::set_var("PATH_B", "/a:/b:/a");
+        env::remove_var("PATH_C");
+        {
+            let _c = ScopedEnv::remove_path_entry("PATH_B", "/a").unwrap();
+            let _d = ScopedEnv::remove_path_entry("PATH_C", "/a").unwrap();
+            assert_eq!(env::var("PATH_B").unwrap(), "/b");
+            assert_eq!(env::var_os("PATH_C"), None);
+        }
+
+        assert_eq!(env::var("PATH_B").unwrap(), "/a:/b:/a");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn does_keep_non_utf8_entries() {
+        use std::ffi::OsString;
+        use std::os::unix::ffi::OsStringExt;
+
+        let _lock = lock();
+        let old_value = OsString::from_vec(b"/caf\xe9:/bin".to_vec());
+        env::set_var("PATH_D", &old_value);
+        {
+            let _c = ScopedEnv::remove_path_entry("PATH_D", "/bin").unwrap();
+            assert_eq!(
+                env::var_os("PATH_D").unwrap(),
+                OsString::from_vec(b"/caf\xe9".to_vec())
+            );
+        }
+
+        assert_eq!(env::var_os("PATH_D").unwrap(), old_value);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn rejects_entries_containing_separator() {
+        let err = ScopedEnv::prepend_path("PATH_E", "/a:/b").err().unwrap();
+        assert_eq!(
+            err,
+            ScopedEnvError::PathEntryContainsSeparator {
+                name: "PATH_E".into(),
+                entry: "/a:/b".into()
+            }
+        );
+        assert_eq!(env::var_os("PATH_E"), None);
+    }
+}
@@ -0,0 +1,265 @@
+use std::collections::BTreeMap;
+use std::env::{self, VarError};
+use std::ffi::{OsStr, OsString};
+use std::iter::FromIterator;
+
+use crate::{overlay, EnvSnapshot};
+
+/// A source of environment variables. Code that takes a
+/// `&dyn EnvProvider` instead of reading `std::env` directly can be
+/// given a [`MapEnv`] in tests, with no global state involved.
+///
+/// ```rust
+/// use scoped_env::{EnvProvider, MapEnv};
+/// use std::ffi::OsStr;
+///
+/// fn port(env: &dyn EnvProvider) -> u16 {
+///     env.var(OsStr::new("PORT")).ok().and_then(|port| port.parse().ok()).unwrap_or(80)
+/// }
+///
+/// let env = MapEnv::new().with("PORT", "8080");
+/// assert_eq!(port(&env), 8080);
+/// ```
+pub trait EnvProvider {
+    /// Fetches the variable {key}.
+    fn get(&self, key: &OsStr) -> Option<OsString>;
+
+    /// Iterates over every variable this provider holds.
+    fn iter(&self) -> Box<dyn Iterator<Item = (OsString, OsString)> + '_>;
+
+    /// Fetches the variable {key} as a `String`, like
+    /// [`std::env::var`].
+    #[track_caller]
+    fn var(&self, key: &OsStr) -> Result<String, VarError> {
+        match self.get(key) {
+            Some(value) => value.into_string().map_err(VarError::NotUnicode),
+            None => Err(VarError::NotPresent),
+        }
+    }
+}
+
+impl<P: EnvProvider + ?Sized> EnvProvider for &P {
+    #[track_caller]
+    fn get(&self, key: &OsStr) -> Option<OsString> {
+        (**self).get(key)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (OsString, OsString)> + '_> {
+        (**self).iter()
+    }
+}
+
+impl<P: EnvProvider + ?Sized> EnvProvider for Box<P> {
+    #[track_caller]
+    fn get(&self, key: &OsStr) -> Option<OsString> {
+        (**self).get(key)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (OsString, OsString)> + '_> {
+        (**self).iter()
+    }
+}
+
+/// The real environment of the currently running process.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ProcessEnv;
+
+impl EnvProvider for ProcessEnv {
+    fn get(&self, key: &OsStr) -> Option<OsString> {
+        env::var_os(key)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (OsString, OsString)> + '_> {
+        Box::new(env::vars_os())
+    }
+}
+
+/// The real environment of the currently running process as seen
+/// through the [`overlay`](crate::overlay) of the current thread.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OverlayEnv;
+
+impl EnvProvider for OverlayEnv {
+    fn get(&self, key: &OsStr) -> Option<OsString> {
+        overlay::var_os(key)
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (OsString, OsString)> + '_> {
+        Box::new(overlay::vars_os())
+    }
+}
+
+/// An in-memory set of environment variables.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MapEnv {
+    vars: BTreeMap<OsString, OsString>,
+}
+
+impl MapEnv {
+    /// Creates an empty set of variables.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the variable {name} with {value}, replacing any
+    /// previous value.
+    pub fn with<K: AsRef<OsStr>, V: AsRef<OsStr>>(mut self, name: K, value: V) -> Self {
+        self.insert(name, value);
+        self
+    }
+
+    /// Sets the variable {name} to {value}, returning the previous
+    /// value.
+    pub fn insert<K: AsRef<OsStr>, V: AsRef<OsStr>>(
+        &mut self,
+        name: K,
+        value: V,
+    ) -> Option<OsString> {
+        self.vars
+            .insert(name.as_ref().to_os_string(), value.as_ref().to_os_string())
+    }
+
+    /// Removes the variable {name}, returning its value.
+    pub fn remove<K: AsRef<OsStr>>(&mut self, name: K) -> Option<OsString> {
+        self.vars.remove(name.as_ref())
+    }
+}
+
+impl<K: AsRef<OsStr>, V: AsRef<OsStr>> FromIterator<(K, V)> for MapEnv {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(vars: I) -> Self {
+        let mut env = Self::new();
+        for (name, value) in vars {
+            env.insert(name, value);
+        }
+        env
+    }
+}
+
+impl From<BTreeMap<OsString, OsString>> for MapEnv {
+    fn from(vars: BTreeMap<OsString, OsString>) -> Self {
+        Self { vars }
+    }
+}
+
+impl From<&EnvSnapshot> for MapEnv {
+    fn from(snapshot: &EnvSnapshot) -> Self {
+        snapshot.iter().collect()
+    }
+}
+
+impl EnvProvider for MapEnv {
+    fn get(&self, key: &OsStr) -> Option<OsString> {
+        self.vars.get(key).cloned()
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (OsString, OsString)> + '_> {
+        Box::new(
+            self.vars
+                .iter()
+                .map(|(name, value)| (name.clone(), value.clone())),
+        )
+    }
+}
+
+/// A stack of providers. Lookups go through the layers from the
+/// most recently added to the first, so later layers shadow
+/// earlier ones.
+///
+/// ```rust
+/// use scoped_env::{EnvProvider, LayeredEnv, MapEnv, ProcessEnv};
+/// use std::ffi::OsStr;
+///
+/// let env = LayeredEnv::new()
+///     .with_layer(ProcessEnv)
+///     .with_layer(MapEnv::new().with("HOME", "/tmp/home"));
+/// assert_eq!(env.var(OsStr::new("HOME")).unwrap().as_str(), "/tmp/home");
+/// ```
+#[derive(Default)]
+pub struct LayeredEnv<'a> {
+    layers: Vec<Box<dyn EnvProvider + 'a>>,
+}
+
+impl<'a> LayeredEnv<'a> {
+    /// Creates a stack with no layers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds {provider} on top of the existing layers.
+    pub fn with_layer<P: EnvProvider + 'a>(mut self, provider: P) -> Self {
+        self.layers.push(Box::new(provider));
+        self
+    }
+}
+
+impl EnvProvider for LayeredEnv<'_> {
+    #[track_caller]
+    fn get(&self, key: &OsStr) -> Option<OsString> {
+        for layer in self.layers.iter().rev() {
+            if let Some(value) = layer.get(key) {
+                return Some(value);
+            }
+        }
+        None
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (OsString, OsString)> + '_> {
+        let mut vars = BTreeMap::new();
+        for layer in &self.layers {
+            vars.extend(layer.iter());
+        }
+        Box::new(vars.into_iter())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ScopedEnv;
+
+    #[test]
+    fn process_env_reads_the_environment() {
+        let _c = ScopedEnv::set("PROVIDER_A", "hello");
+        assert_eq!(ProcessEnv.get(OsStr::new("PROVIDER_A")).unwrap(), "hello");
+        assert!(ProcessEnv.iter().any(|(name, _)| name == "PROVIDER_A"));
+    }
+
+    #[test]
+    fn map_env_holds_its_own_values() {
+        let mut env: MapEnv = vec![("A", "1"), ("B", "2")].into_iter().collect();
+        assert_eq!(env.insert("A", "3").unwrap(), "1");
+        assert_eq!(env.remove("B").unwrap(), "2");
+        assert_eq!(env.var(OsStr::new("A")).unwrap(), "3");
+        assert_eq!(env.var(OsStr::new("B")), Err(VarError::NotPresent));
+        assert_eq!(
+            env.iter().collect::<Vec<_>>(),
+            vec![("A".into(), "3".into())]
+        );
+    }
+
+    #[test]
+    fn overlay_env_reads_through_the_overlay() {
+        let _c = overlay::set("PROVIDER_B", "hello");
+        assert_eq!(OverlayEnv.get(OsStr::new("PROVIDER_B")).unwrap(), "hello");
+        assert_eq!(ProcessEnv.get(OsStr::new("PROVIDER_B")), None);
+    }
+
+    #[test]
+    fn layered_env_prefers_later_layers() {
+        let base = MapEnv::new().with("A", "base").with("B", "base");
+        let env = LayeredEnv::new()
+            .with_layer(&base)
+            .with_layer(MapEnv::new().with("B", "top").with("C", "top"));
+
+        assert_eq!(env.get(OsStr::new("A")).unwrap(), "base");
+        assert_eq!(env.get(OsStr::new("B")).unwrap(), "top");
+        assert_eq!(
+            env.iter().collect::<Vec<_>>(),
+            vec![
+                ("A".into(), "base".into()),
+                ("B".into(), "top".into()),
+                ("C".into(), "top".into()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,198 @@
+use std::ffi::{OsStr, OsString};
+use std::panic::Location;
+use std::sync::{Mutex, PoisonError};
+
+use crate::EnvProvider;
+
+/// A single lookup made through a [`RecordingEnv`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnvRead {
+    /// The variable that was looked up.
+    pub key: OsString,
+    /// Whether the variable was set.
+    pub hit: bool,
+    /// Where the lookup was made from, if call sites are being
+    /// recorded.
+    pub location: Option<&'static Location<'static>>,
+}
+
+/// Wraps another provider and records every variable looked up
+/// through it, so tests can check which variables a component
+/// actually depends on. Listing every variable with
+/// [`EnvProvider::iter`] is not recorded.
+///
+/// ```rust
+/// use scoped_env::{EnvProvider, MapEnv, RecordingEnv};
+/// use std::ffi::OsStr;
+///
+/// let env = RecordingEnv::new(MapEnv::new().with("DATABASE_URL", "postgres://"));
+/// env.get(OsStr::new("DATABASE_URL"));
+/// env.assert_read("DATABASE_URL");
+/// env.assert_not_read("LEGACY_FLAG");
+/// ```
+pub struct RecordingEnv<P> {
+    inner: P,
+    call_sites: bool,
+    reads: Mutex<Vec<EnvRead>>,
+}
+
+impl<P: EnvProvider> RecordingEnv<P> {
+    /// Records lookups made through {inner}.
+    pub fn new(inner: P) -> Self {
+        Self {
+            inner,
+            call_sites: false,
+            reads: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Also records where each lookup was made from.
+    pub fn with_call_sites(mut self) -> Self {
+        self.call_sites = true;
+        self
+    }
+
+    /// Every lookup made so far, in order.
+    pub fn reads(&self) -> Vec<EnvRead> {
+        self.reads
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Whether {key} has been looked up, whether or not it was set.
+    pub fn was_read<K: AsRef<OsStr>>(&self, key: K) -> bool {
+        let key = key.as_ref();
+        self.reads
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .iter()
+            .any(|read| read.key == key)
+    }
+
+    /// Panics unless {key} has been looked up.
+    #[track_caller]
+    pub fn assert_read<K: AsRef<OsStr>>(&self, key: K) {
+        let key = key.as_ref();
+        if !self.was_read(key) {
+            panic!(
+                "expected environment variable {:?} to be read, but it was not",
+                key
+            );
+        }
+    }
+
+    /// Panics if {key} has been looked up, naming where it was
+    /// read from when call sites are being recorded.
+    #[track_caller]
+    pub fn assert_not_read<K: AsRef<OsStr>>(&self, key: K) {
+        let key = key.as_ref();
+        let reads = self.reads();
+        if let Some(read) = reads.iter().find(|read| read.key == key) {
+            match read.location {
+                Some(location) => panic!(
+                    "expected environment variable {:?} not to be read, but it was read at {}",
+                    key, location
+                ),
+                None => panic!(
+                    "expected environment variable {:?} not to be read, but it was",
+                    key
+                ),
+            }
+        }
+    }
+
+    /// Forgets every lookup recorded so far.
+    pub fn clear(&self) {
+        self.reads
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+    }
+
+    /// Unwraps the provider being recorded.
+    pub fn into_inner(self) -> P {
+        self.inner
+    }
+}
+
+impl<P: EnvProvider> EnvProvider for RecordingEnv<P> {
+    #[track_caller]
+    fn get(&self, key: &OsStr) -> Option<OsString> {
+        let location = Location::caller();
+        let value = self.inner.get(key);
+        self.reads
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .push(EnvRead {
+                key: key.to_os_string(),
+                hit: value.is_some(),
+                location: if self.call_sites {
+                    Some(location)
+                } else {
+                    None
+                },
+            });
+        value
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (OsString, OsString)> + '_> {
+        self.inner.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{overlay, MapEnv, OverlayEnv};
+    use std::panic;
+
+    #[test]
+    fn does_record_hits_and_misses() {
+        let env = RecordingEnv::new(MapEnv::new().with("A", "1"));
+        assert_eq!(env.get(OsStr::new("A")).unwrap(), "1");
+        assert_eq!(env.get(OsStr::new("B")), None);
+
+        assert_eq!(
+            env.reads(),
+            vec![
+                EnvRead {
+                    key: "A".into(),
+                    hit: true,
+                    location: None
+                },
+                EnvRead {
+                    key: "B".into(),
+                    hit: false,
+                    location: None
+                },
+            ]
+        );
+        env.assert_read("B");
+        env.assert_not_read("C");
+        env.clear();
+        assert!(!env.was_read("A"));
+    }
+
+    #[test]
+    fn does_record_call_sites() {
+        let _c = overlay::set("RECORDING_A", "hello");
+        let env = RecordingEnv::new(OverlayEnv).with_call_sites();
+        let provider: &dyn EnvProvider = &env;
+        let line = line!() + 1;
+        assert_eq!(provider.var(OsStr::new("RECORDING_A")).unwrap(), "hello");
+
+        let location = env.reads()[0].location.unwrap();
+        assert_eq!((location.file(), location.line()), (file!(), line));
+
+        let result = panic::catch_unwind(|| env.assert_not_read("RECORDING_A"));
+        let payload = result.unwrap_err();
+        assert_eq!(
+            payload.downcast_ref::<String>().unwrap(),
+            &format!(
+                "expected environment variable \"RECORDING_A\" not to be read, but it was read at {}",
+                location
+            )
+        );
+    }
+}
@@ -0,0 +1,216 @@
+use std::env;
+use std::ffi::{OsStr, OsString};
+
+use crate::error;
+use crate::{lock, ScopedEnv, ScopedEnvSet};
+
+impl ScopedEnv<OsString> {
+    /// Removes every environment variable whose name is not in
+    /// {allow}, returning a single guard over all of them. When the
+    /// guard goes out of scope the whole previous environment is
+    /// restored: removed variables come back, and variables added
+    /// or changed in the meantime are removed or reset.
+    ///
+    /// ```rust
+    /// use scoped_env::ScopedEnv;
+    /// let _lock = scoped_env::lock();
+    /// std::env::set_var("HELLO", "WORLD");
+    /// {
+    ///     let _c = ScopedEnv::hermetic(&["PATH", "HOME", "TMPDIR"]);
+    ///     assert!(std::env::var("HELLO").is_err());
+    /// }
+    /// assert_eq!(std::env::var("HELLO").unwrap().as_str(), "WORLD");
+    /// ```
+    pub fn hermetic(allow: &[&str]) -> ScopedEnvSet {
+        Self::remove_where(|name| !allow.iter().any(|allowed| OsStr::new(allowed) == name))
+            .restore_rest_on_drop()
+    }
+
+    /// Removes every environment variable whose name matches the
+    /// glob {pattern}, where `*` matches any run of characters and
+    /// `?` matches exactly one. The variables are those present
+    /// when this is called, and all of them are restored when the
+    /// returned guard goes out of scope.
+    ///
+    /// ```rust
+    /// use scoped_env::ScopedEnv;
+    /// let _c = ScopedEnv::set("AWS_REGION", "eu-west-1");
+    /// {
+    ///     let _d = ScopedEnv::remove_matching("AWS_*");
+    ///     assert!(std::env::var("AWS_REGION").is_err());
+    /// }
+    /// assert_eq!(std::env::var("AWS_REGION").unwrap().as_str(), "eu-west-1");
+    /// ```
+    pub fn remove_matching(pattern: &str) -> ScopedEnvSet {
+        let pattern: Vec<char> = pattern.chars().collect();
+        Self::remove_where(|name| {
+            let name: Vec<char> = name.to_string_lossy().chars().collect();
+            glob_matches(&pattern, &name)
+        })
+    }
+
+    /// Removes every environment variable whose whole name is
+    /// matched by {pattern}, that is whose match found by
+    /// [`regex::Regex::find`] starts at the first character and ends
+    /// at the last. Options set through `regex::RegexBuilder`, such
+    /// as case insensitivity, are kept. See
+    /// [`ScopedEnv::remove_matching`].
+    ///
+    /// ```rust
+    /// use scoped_env::ScopedEnv;
+    /// let pattern = regex::Regex::new("(KUBE|OTEL_).*").unwrap();
+    /// let _c = ScopedEnv::remove_matching_regex(&pattern);
+    /// ```
+    #[cfg(feature = "regex")]
+    pub fn remove_matching_regex(pattern: &regex::Regex) -> ScopedEnvSet {
+        Self::remove_where(|name| {
+            let name = name.to_string_lossy();
+            pattern
+                .find(&name)
+                .is_some_and(|found| found.start() == 0 && found.end() == name.len())
+        })
+    }
+
+    fn remove_where<F>(mut remove: F) -> ScopedEnvSet
+    where
+        F: FnMut(&OsStr) -> bool,
+    {
+        let _lock = lock();
+        let names: Vec<OsString> = env::vars_os()
+            .map(|(name, _)| name)
+            .filter(|name| error::validate_name(name).is_ok() && remove(name))
+            .collect();
+
+        ScopedEnvSet::new(names.into_iter().map(|name| (name, None::<OsString>)))
+    }
+}
+
+/// Whether {name} matches {pattern} in full, where `*` matches any
+/// run of characters and `?` matches exactly one.
+fn glob_matches(pattern: &[char], name: &[char]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // Where to resume if the current attempt fails: the position
+    // just after the last `*` and the name position it matched up
+    // to.
+    let mut backtrack = None;
+    while n < name.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                p += 1;
+                backtrack = Some((p, n));
+            }
+            Some(&c) if c == '?' || c == name[n] => {
+                p += 1;
+                n += 1;
+            }
+            _ => match backtrack {
+                Some((star_p, star_n)) => {
+                    p = star_p;
+                    n = star_n + 1;
+                    backtrack = Some((star_p, star_n + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn does_keep_only_the_allowlist() {
+        let _lock = lock();
+        env::set_var("REMOVE_A", "kept");
+        env::set_var("REMOVE_B", "removed");
+        {
+            let _c = ScopedEnv::hermetic(&["REMOVE_A"]);
+            assert_eq!(
+                env::vars_os().collect::<Vec<_>>(),
+                vec![("REMOVE_A".into(), "kept".into())]
+            );
+            env::set_var("REMOVE_A", "changed");
+            env::set_var("REMOVE_ADDED", "added");
+        }
+
+        assert_eq!(env::var("REMOVE_A").unwrap(), "kept");
+        assert_eq!(env::var("REMOVE_B").unwrap(), "removed");
+        assert_eq!(env::var_os("REMOVE_ADDED"), None);
+        env::remove_var("REMOVE_A");
+        env::remove_var("REMOVE_B");
+    }
+
+    #[test]
+    fn does_match_globs() {
+        let matches = |pattern: &str, name: &str| {
+            let pattern: Vec<char> = pattern.chars().collect();
+            let name: Vec<char> = name.chars().collect();
+            glob_matches(&pattern, &name)
+        };
+
+        assert!(matches("AWS_*", "AWS_REGION"));
+        assert!(matches("AWS_*", "AWS_"));
+        assert!(!matches("AWS_*", "MY_AWS_REGION"));
+        assert!(matches("KUBE*", "KUBECONFIG"));
+        assert!(matches("*_TOKEN", "GITHUB_TOKEN"));
+        assert!(matches("A?C", "ABC"));
+        assert!(!matches("A?C", "AC"));
+        assert!(matches("A*B*C", "AXXBYBC"));
+        assert!(!matches("A*B*C", "AXXBYB"));
+        assert!(matches("PATH", "PATH"));
+        assert!(!matches("PATH", "PATHS"));
+    }
+
+    #[test]
+    fn does_remove_and_restore_matching() {
+        let _lock = lock();
+        env::set_var("REMOVE_FAMILY_A", "1");
+        env::set_var("REMOVE_FAMILY_B", "2");
+        env::set_var("REMOVE_OTHER", "3");
+        {
+            let _c = ScopedEnv::remove_matching("REMOVE_FAMILY_*");
+            assert_eq!(env::var_os("REMOVE_FAMILY_A"), None);
+            assert_eq!(env::var_os("REMOVE_FAMILY_B"), None);
+            assert_eq!(env::var("REMOVE_OTHER").unwrap(), "3");
+        }
+
+        assert_eq!(env::var("REMOVE_FAMILY_A").unwrap(), "1");
+        assert_eq!(env::var("REMOVE_FAMILY_B").unwrap(), "2");
+        env::remove_var("REMOVE_FAMILY_A");
+        env::remove_var("REMOVE_FAMILY_B");
+        env::remove_var("REMOVE_OTHER");
+    }
+
+    #[cfg(feature = "regex")]
+    #[test]
+    fn does_remove_matching_regex() {
+        let _lock = lock();
+        env::set_var("REMOVE_REGEX_1", "1");
+        env::set_var("REMOVE_REGEX_X", "2");
+        env::set_var("MY_REMOVE_REGEX_2", "3");
+        {
+            let pattern = regex::Regex::new("REMOVE_REGEX_[0-9]+").unwrap();
+            let _c = ScopedEnv::remove_matching_regex(&pattern);
+            assert_eq!(env::var_os("REMOVE_REGEX_1"), None);
+            assert_eq!(env::var("REMOVE_REGEX_X").unwrap(), "2");
+            assert_eq!(env::var("MY_REMOVE_REGEX_2").unwrap(), "3");
+        }
+        {
+            let pattern = regex::RegexBuilder::new("remove_regex_x")
+                .case_insensitive(true)
+                .build()
+                .unwrap();
+            let _c = ScopedEnv::remove_matching_regex(&pattern);
+            assert_eq!(env::var_os("REMOVE_REGEX_X"), None);
+            assert_eq!(env::var("REMOVE_REGEX_1").unwrap(), "1");
+        }
+
+        assert_eq!(env::var("REMOVE_REGEX_1").unwrap(), "1");
+        env::remove_var("REMOVE_REGEX_1");
+        env::remove_var("REMOVE_REGEX_X");
+        env::remove_var("MY_REMOVE_REGEX_2");
+    }
+}
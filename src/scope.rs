@@ -0,0 +1,150 @@
+use std::ffi::OsStr;
+#[cfg(feature = "tokio")]
+use std::ffi::OsString;
+#[cfg(feature = "tokio")]
+use std::future::{self, Future};
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::ScopedEnvSet;
+#[cfg(feature = "tokio")]
+use crate::{lock, set};
+
+/// Runs {body} with every `(name, value)` pair in {vars} applied
+/// to the environment, returning whatever {body} returns. A value
+/// of `Some` sets the variable and a value of `None` removes it.
+///
+/// The variables are restored when {body} returns, and also when
+/// it panics, in which case the panic is resumed once the
+/// environment has been cleaned up.
+///
+/// ```rust
+/// use scoped_env::with_env;
+/// let value = with_env([("HELLO", Some("WORLD")), ("FOO", None)], || {
+///     std::env::var("HELLO").unwrap()
+/// });
+/// assert_eq!(value.as_str(), "WORLD");
+/// assert!(std::env::var("HELLO").is_err());
+/// ```
+pub fn with_env<I, K, V, F, R>(vars: I, body: F) -> R
+where
+    I: IntoIterator<Item = (K, Option<V>)>,
+    K: AsRef<OsStr>,
+    V: AsRef<OsStr>,
+    F: FnOnce() -> R,
+{
+    let guard = ScopedEnvSet::new(vars);
+    let result = panic::catch_unwind(AssertUnwindSafe(body));
+    drop(guard);
+
+    match result {
+        Ok(value) => value,
+        Err(payload) => panic::resume_unwind(payload),
+    }
+}
+
+/// Runs {future} to completion with every `(name, value)` pair in
+/// {vars} applied to the environment, returning its output. A value
+/// of `Some` sets the variable and a value of `None` removes it.
+///
+/// Unlike holding a [`ScopedEnvSet`] across an `.await`, waiting for
+/// the environment lock does not block the executor thread, so other
+/// tasks keep running while this one waits its turn. Guards created
+/// while {future} is being polled share its lock rather than waiting
+/// on it. The variables are restored when {future} completes, panics
+/// or is dropped.
+///
+/// ```rust
+/// # #[tokio::main(flavor = "current_thread")]
+/// # async fn main() {
+/// use scoped_env::with_env_async;
+/// let value = with_env_async([("HELLO", Some("WORLD"))], async {
+///     std::env::var("HELLO").unwrap()
+/// })
+/// .await;
+/// assert_eq!(value.as_str(), "WORLD");
+/// # }
+/// ```
+#[cfg(feature = "tokio")]
+pub async fn with_env_async<I, K, V, F>(vars: I, future: F) -> F::Output
+where
+    I: IntoIterator<Item = (K, Option<V>)>,
+    K: AsRef<OsStr>,
+    V: AsRef<OsStr>,
+    F: Future,
+{
+    let vars: Vec<(OsString, Option<OsString>)> = set::os_vars(vars).collect();
+
+    let (_lock, owner) = lock::lock_async().await;
+    let _guard = lock::with_owner(owner, || ScopedEnvSet::new(vars));
+    let mut future = Box::pin(future);
+    future::poll_fn(|cx| lock::with_owner(owner, || future.as_mut().poll(cx))).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lock;
+    use std::env;
+
+    #[test]
+    fn does_return_body_value() {
+        let value = with_env(vec![("WITH_A", Some("1"))], || env::var("WITH_A").unwrap());
+        assert_eq!(value, "1");
+    }
+
+    #[test]
+    fn does_restore_on_panic() {
+        let _lock = lock();
+        env::set_var("WITH_B", "OLD_VALUE");
+        let result = panic::catch_unwind(|| {
+            with_env(vec![("WITH_B", None::<&str>)], || {
+                assert_eq!(env::var_os("WITH_B"), None);
+                panic!("boom");
+            })
+        });
+
+        let payload = result.unwrap_err();
+        assert_eq!(payload.downcast_ref::<&str>(), Some(&"boom"));
+        assert_eq!(env::var("WITH_B").unwrap(), "OLD_VALUE");
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn does_hold_across_await() {
+        use std::time::Duration;
+
+        let first = tokio::spawn(with_env_async(vec![("WITH_C", Some("1"))], async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            env::var("WITH_C").unwrap()
+        }));
+        let second = tokio::spawn(with_env_async(vec![("WITH_C", Some("2"))], async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            env::var("WITH_C").unwrap()
+        }));
+
+        assert_eq!(first.await.unwrap(), "1");
+        assert_eq!(second.await.unwrap(), "2");
+        assert_eq!(env::var_os("WITH_C"), None);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn does_share_lock_with_guards_inside() {
+        let value = with_env_async(vec![("WITH_D", Some("outer"))], async {
+            tokio::task::yield_now().await;
+            let _c = crate::ScopedEnv::set("WITH_E", "inner");
+            let inner = with_env_async(vec![("WITH_D", Some("nested"))], async {
+                env::var("WITH_D").unwrap()
+            })
+            .await;
+            (
+                inner,
+                env::var("WITH_D").unwrap(),
+                env::var("WITH_E").unwrap(),
+            )
+        })
+        .await;
+
+        assert_eq!(value, ("nested".into(), "outer".into(), "inner".into()));
+    }
+}
@@ -0,0 +1,355 @@
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::iter::FromIterator;
+use std::path::Path;
+
+use crate::dotenv::{self, DotenvError};
+use crate::interpolate;
+use crate::{lock, restore_var, EnvLock, EnvSnapshot, ScopedEnv, TamperPolicy};
+
+/// A group of scoped environment variables that are applied
+/// together and restored together. When an instance goes out
+/// of scope every variable is restored, in the reverse order
+/// that it was applied.
+pub struct ScopedEnvSet {
+    vars: Vec<(OsString, Option<OsString>)>,
+    guards: Vec<ScopedEnv<OsString>>,
+    /// The rest of the environment, put back on drop, for sets that
+    /// own every variable rather than only their own.
+    rest: Option<EnvSnapshot>,
+    _lock: EnvLock,
+}
+
+impl ScopedEnvSet {
+    /// Applies every `(name, value)` pair in {vars}. A value of
+    /// `Some` sets the variable and a value of `None` removes it.
+    /// The returned instance should be assigned to a `_name`
+    /// binding so that it lasts as long as the current block.
+    ///
+    /// ```rust
+    /// use scoped_env::ScopedEnvSet;
+    /// let _lock = scoped_env::lock();
+    /// std::env::set_var("HELLO", "WORLD");
+    /// {
+    ///     let _c = ScopedEnvSet::new(vec![("FOO", Some("BAR")), ("HELLO", None)]);
+    ///     assert_eq!(std::env::var("FOO").unwrap().as_str(), "BAR");
+    ///     assert!(std::env::var("HELLO").is_err());
+    /// }
+    /// assert_eq!(std::env::var("HELLO").unwrap().as_str(), "WORLD");
+    /// ```
+    pub fn new<I, K, V>(vars: I) -> Self
+    where
+        I: IntoIterator<Item = (K, Option<V>)>,
+        K: AsRef<OsStr>,
+        V: AsRef<OsStr>,
+    {
+        let vars: Vec<(OsString, Option<OsString>)> = os_vars(vars).collect();
+
+        let _lock = lock();
+        let guards = vars
+            .iter()
+            .map(|(name, value)| match value {
+                Some(value) => ScopedEnv::set(name.clone(), value),
+                None => ScopedEnv::remove(name.clone()),
+            })
+            .collect();
+
+        Self {
+            vars,
+            guards,
+            rest: None,
+            _lock,
+        }
+    }
+
+    /// Records the current environment so that, on drop, every
+    /// variable outside this set is put back as it is now, including
+    /// removing variables added in the meantime.
+    pub(crate) fn restore_rest_on_drop(mut self) -> Self {
+        self.rest = Some(EnvSnapshot::capture());
+        self
+    }
+
+    /// Starts a list of variables to apply later. See
+    /// [`ScopedEnvSetBuilder`].
+    pub fn builder() -> ScopedEnvSetBuilder {
+        ScopedEnvSetBuilder::default()
+    }
+
+    /// Uses {policy} instead of the default [`TamperPolicy`] for
+    /// every variable in the set.
+    pub fn on_tamper(mut self, policy: TamperPolicy) -> Self {
+        for guard in &mut self.guards {
+            guard.policy = Some(policy);
+        }
+        self
+    }
+
+    /// Reads the `.env` file at {path} and applies every entry in
+    /// it, in the order they appear in the file.
+    ///
+    /// The usual dotenv syntax is supported: blank lines and `#`
+    /// comments, an optional `export ` prefix, unquoted values,
+    /// single quoted values which are taken literally, and double
+    /// quoted values which understand `\n`, `\r`, `\t`, `\"`, `\'`,
+    /// `\\` and `\$` escapes. Quoted values may span multiple lines.
+    /// A value holding a NUL character, which the environment cannot
+    /// store, is reported as a parse error like any other.
+    ///
+    /// ```rust,no_run
+    /// use scoped_env::ScopedEnvSet;
+    /// let _c = ScopedEnvSet::from_dotenv("tests/fixtures/.env").unwrap();
+    /// ```
+    pub fn from_dotenv<P: AsRef<Path>>(path: P) -> Result<Self, DotenvError> {
+        Ok(ScopedEnvSetBuilder::from_dotenv(path)?.apply())
+    }
+}
+
+/// Every `(name, value)` pair in the set, in the order they were
+/// applied.
+impl AsRef<[(OsString, Option<OsString>)]> for ScopedEnvSet {
+    fn as_ref(&self) -> &[(OsString, Option<OsString>)] {
+        &self.vars
+    }
+}
+
+impl<K, V> FromIterator<(K, Option<V>)> for ScopedEnvSet
+where
+    K: AsRef<OsStr>,
+    V: AsRef<OsStr>,
+{
+    fn from_iter<I: IntoIterator<Item = (K, Option<V>)>>(vars: I) -> Self {
+        Self::new(vars)
+    }
+}
+
+/// A list of environment variable changes that has not been
+/// applied yet. It can be applied to the current process with
+/// [`ScopedEnvSetBuilder::apply`], or handed to a child process
+/// with [`CommandEnvExt`](crate::CommandEnvExt) without touching
+/// the current process at all.
+///
+/// ```rust
+/// use scoped_env::ScopedEnvSet;
+/// let vars = ScopedEnvSet::builder().set("HELLO", "WORLD").remove("FOO");
+/// let _c = vars.apply();
+/// assert_eq!(std::env::var("HELLO").unwrap().as_str(), "WORLD");
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ScopedEnvSetBuilder {
+    vars: Vec<(OsString, Option<OsString>)>,
+}
+
+impl ScopedEnvSetBuilder {
+    /// Starts an empty list.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds setting the variable {name} to {value}.
+    pub fn set<K: AsRef<OsStr>, V: AsRef<OsStr>>(mut self, name: K, value: V) -> Self {
+        let name = name.as_ref().to_os_string();
+        self.vars.push((name, Some(value.as_ref().to_os_string())));
+        self
+    }
+
+    /// Adds removing the variable {name}.
+    pub fn remove<K: AsRef<OsStr>>(mut self, name: K) -> Self {
+        self.vars.push((name.as_ref().to_os_string(), None));
+        self
+    }
+
+    /// Reads every entry of the `.env` file at {path}, in the order
+    /// they appear in the file. See [`ScopedEnvSet::from_dotenv`]
+    /// for the supported syntax.
+    pub fn from_dotenv<P: AsRef<Path>>(path: P) -> Result<Self, DotenvError> {
+        let source = fs::read_to_string(path)?;
+        let entries = dotenv::parse(&source)?;
+        Ok(entries
+            .into_iter()
+            .map(|(name, value)| (name, Some(value)))
+            .collect())
+    }
+
+    /// Reads every entry of the `.env` file at {path} like
+    /// [`ScopedEnvSetBuilder::from_dotenv`], expanding `${VAR}`,
+    /// `${VAR:-default}` and `${VAR:?message}` in unquoted and
+    /// double quoted values as they are read. Single quoted values
+    /// and `\$` escapes stay literal. See
+    /// [`ScopedEnvSetBuilder::interpolate`] for how references are
+    /// resolved.
+    ///
+    /// ```rust,no_run
+    /// use scoped_env::ScopedEnvSetBuilder;
+    /// let _c = ScopedEnvSetBuilder::from_dotenv_interpolated("tests/fixtures/.env")
+    ///     .unwrap()
+    ///     .apply();
+    /// ```
+    pub fn from_dotenv_interpolated<P: AsRef<Path>>(path: P) -> Result<Self, DotenvError> {
+        let source = fs::read_to_string(path)?;
+        let entries: Vec<(OsString, Option<OsString>)> = dotenv::parse_template(&source)?
+            .into_iter()
+            .map(|(name, value)| (name.into(), Some(value.into())))
+            .collect();
+        Ok(interpolate::interpolate(&entries)?.into_iter().collect())
+    }
+
+    /// Applies every variable in the list, in order.
+    pub fn apply(self) -> ScopedEnvSet {
+        ScopedEnvSet::new(self.vars)
+    }
+}
+
+impl<K, V> FromIterator<(K, Option<V>)> for ScopedEnvSetBuilder
+where
+    K: AsRef<OsStr>,
+    V: AsRef<OsStr>,
+{
+    fn from_iter<I: IntoIterator<Item = (K, Option<V>)>>(vars: I) -> Self {
+        Self {
+            vars: os_vars(vars).collect(),
+        }
+    }
+}
+
+/// Converts every `(name, value)` pair in {vars} to owned names and
+/// values, as taken by every function that accepts a list of
+/// variables.
+pub(crate) fn os_vars<I, K, V>(vars: I) -> impl Iterator<Item = (OsString, Option<OsString>)>
+where
+    I: IntoIterator<Item = (K, Option<V>)>,
+    K: AsRef<OsStr>,
+    V: AsRef<OsStr>,
+{
+    vars.into_iter().map(|(name, value)| {
+        let value = value.map(|value| value.as_ref().to_os_string());
+        (name.as_ref().to_os_string(), value)
+    })
+}
+
+/// Every `(name, value)` pair in the list, in the order they were
+/// added.
+impl AsRef<[(OsString, Option<OsString>)]> for ScopedEnvSetBuilder {
+    fn as_ref(&self) -> &[(OsString, Option<OsString>)] {
+        &self.vars
+    }
+}
+
+impl Drop for ScopedEnvSet {
+    fn drop(&mut self) {
+        if let Some(rest) = &self.rest {
+            // Variables in the set are left to their guards, which
+            // check them for tampering.
+            let owned = |name: &OsStr| self.vars.iter().any(|(owned, _)| owned == name);
+            for (name, value) in env::vars_os() {
+                if !owned(&name) && rest.get(&name) != Some(value.as_os_str()) {
+                    restore_var(&name, rest.get(&name));
+                }
+            }
+            for (name, value) in rest.iter() {
+                if !owned(name) && env::var_os(name).is_none() {
+                    restore_var(name, Some(value));
+                }
+            }
+        }
+
+        while let Some(guard) = self.guards.pop() {
+            drop(guard);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    #[test]
+    fn does_apply_all() {
+        let _c = ScopedEnvSet::new(vec![("SET_A", Some("1")), ("SET_B", Some("2"))]);
+        assert_eq!(env::var("SET_A").unwrap(), "1");
+        assert_eq!(env::var("SET_B").unwrap(), "2");
+    }
+
+    #[test]
+    fn does_restore_all_at_end_of_block() {
+        let _lock = lock();
+        env::set_var("SET_C", "OLD_VALUE");
+        env::remove_var("SET_D");
+        {
+            let _c: ScopedEnvSet = vec![("SET_C", None), ("SET_D", Some("new"))]
+                .into_iter()
+                .collect();
+            assert_eq!(env::var_os("SET_C"), None);
+            assert_eq!(env::var("SET_D").unwrap(), "new");
+        }
+
+        assert_eq!(env::var("SET_C").unwrap(), "OLD_VALUE");
+        assert_eq!(env::var_os("SET_D"), None);
+    }
+
+    #[test]
+    fn does_restore_in_reverse_order() {
+        let _lock = lock();
+        env::set_var("SET_E", "OLD_VALUE");
+        {
+            let _c = ScopedEnvSet::new(vec![("SET_E", Some("first")), ("SET_E", Some("second"))]);
+            assert_eq!(env::var("SET_E").unwrap(), "second");
+        }
+
+        assert_eq!(env::var("SET_E").unwrap(), "OLD_VALUE");
+    }
+
+    #[test]
+    fn does_load_dotenv_file() {
+        let path = env::temp_dir().join(format!("scoped-env-{}.env", std::process::id()));
+        fs::write(
+            &path,
+            "# fixture\nexport SET_F=from_file\nSET_G=\"two\nlines\"\n",
+        )
+        .unwrap();
+        let _lock = lock();
+        env::set_var("SET_F", "OLD_VALUE");
+        env::remove_var("SET_G");
+        {
+            let _c = ScopedEnvSet::from_dotenv(&path).unwrap();
+            assert_eq!(env::var("SET_F").unwrap(), "from_file");
+            assert_eq!(env::var("SET_G").unwrap(), "two\nlines");
+        }
+
+        fs::remove_file(&path).unwrap();
+        assert_eq!(env::var("SET_F").unwrap(), "OLD_VALUE");
+        assert_eq!(env::var_os("SET_G"), None);
+        assert!(matches!(
+            ScopedEnvSet::from_dotenv(&path),
+            Err(DotenvError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn does_interpolate_dotenv_file_outside_literals() {
+        let path = env::temp_dir().join(format!(
+            "scoped-env-{}-interpolated.env",
+            std::process::id()
+        ));
+        fs::write(
+            &path,
+            "SET_H='${SET_I}'\nSET_J=\"\\${SET_I}\"\nSET_K=${SET_I}/${SET_L:-x}\nSET_L=later\n",
+        )
+        .unwrap();
+        let _c = ScopedEnv::set("SET_I", "home");
+        let builder = ScopedEnvSetBuilder::from_dotenv_interpolated(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            builder.as_ref(),
+            &[
+                ("SET_H".into(), Some("${SET_I}".into())),
+                ("SET_J".into(), Some("${SET_I}".into())),
+                ("SET_K".into(), Some("home/x".into())),
+                ("SET_L".into(), Some("later".into())),
+            ][..]
+        );
+    }
+}
@@ -0,0 +1,296 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
+
+use crate::{lock, restore_var, EnvLock};
+
+/// A copy of every variable in the environment of the currently
+/// running process at the moment it was captured.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EnvSnapshot {
+    vars: BTreeMap<OsString, OsString>,
+}
+
+impl EnvSnapshot {
+    /// Records every variable currently in the environment.
+    ///
+    /// ```rust
+    /// use scoped_env::{EnvSnapshot, ScopedEnv};
+    /// let _c = ScopedEnv::set("HELLO", "WORLD");
+    /// let snapshot = EnvSnapshot::capture();
+    /// assert_eq!(snapshot.get("HELLO").unwrap(), "WORLD");
+    /// ```
+    pub fn capture() -> Self {
+        let _lock = lock();
+        Self::capture_locked()
+    }
+
+    /// Like [`EnvSnapshot::capture`], for callers that already hold
+    /// the environment lock, possibly on behalf of another thread.
+    pub(crate) fn capture_locked() -> Self {
+        Self {
+            vars: env::vars_os().collect(),
+        }
+    }
+
+    /// The value {name} had when the snapshot was captured.
+    pub fn get<K: AsRef<OsStr>>(&self, name: K) -> Option<&OsStr> {
+        self.vars.get(name.as_ref()).map(OsString::as_os_str)
+    }
+
+    /// Iterates over every captured variable, ordered by name.
+    pub fn iter(&self) -> impl Iterator<Item = (&OsStr, &OsStr)> {
+        self.vars
+            .iter()
+            .map(|(name, value)| (name.as_os_str(), value.as_os_str()))
+    }
+
+    /// Compares this snapshot with a later {other} one, listing
+    /// every variable that was added, removed or changed between
+    /// the two.
+    ///
+    /// ```rust
+    /// use scoped_env::{EnvSnapshot, ScopedEnv};
+    /// let before = EnvSnapshot::capture();
+    /// let _c = ScopedEnv::set("HELLO", "WORLD");
+    /// let diff = before.diff(&EnvSnapshot::capture());
+    /// assert_eq!(diff.added.get(std::ffi::OsStr::new("HELLO")).unwrap(), "WORLD");
+    /// assert_eq!(diff.to_string(), "+ \"HELLO\"=\"WORLD\"\n");
+    /// ```
+    pub fn diff(&self, other: &EnvSnapshot) -> EnvDiff {
+        let mut diff = EnvDiff::default();
+        for (name, old_value) in &self.vars {
+            match other.vars.get(name) {
+                None => {
+                    diff.removed.insert(name.clone(), old_value.clone());
+                }
+                Some(new_value) if new_value != old_value => {
+                    let values = (old_value.clone(), new_value.clone());
+                    diff.changed.insert(name.clone(), values);
+                }
+                Some(_) => {}
+            }
+        }
+
+        for (name, new_value) in &other.vars {
+            if !self.vars.contains_key(name) {
+                diff.added.insert(name.clone(), new_value.clone());
+            }
+        }
+
+        diff
+    }
+
+    /// Puts the environment back to exactly the captured state:
+    /// variables added since are removed, changed variables get
+    /// their captured value back and removed variables are added
+    /// again.
+    pub fn restore(&self) {
+        let _lock = lock();
+        self.restore_locked();
+    }
+
+    /// Like [`EnvSnapshot::restore`], for callers that already hold
+    /// the environment lock, possibly on behalf of another thread.
+    pub(crate) fn restore_locked(&self) {
+        for (name, value) in env::vars_os() {
+            if self.vars.get(&name) != Some(&value) {
+                restore_var(&name, self.get(&name));
+            }
+        }
+
+        for (name, value) in &self.vars {
+            if env::var_os(name).is_none() {
+                restore_var(name, Some(value));
+            }
+        }
+    }
+}
+
+/// The differences between two [`EnvSnapshot`]s, as returned by
+/// [`EnvSnapshot::diff`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EnvDiff {
+    /// Variables only present in the later snapshot, with their
+    /// values.
+    pub added: BTreeMap<OsString, OsString>,
+    /// Variables only present in the earlier snapshot, with the
+    /// values they had.
+    pub removed: BTreeMap<OsString, OsString>,
+    /// Variables present in both snapshots with different values,
+    /// as `(old, new)` pairs.
+    pub changed: BTreeMap<OsString, (OsString, OsString)>,
+}
+
+impl EnvDiff {
+    /// Whether the two snapshots were identical.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+
+    /// Iterates over the name of every variable that differs,
+    /// ordered by name.
+    pub fn names(&self) -> impl Iterator<Item = &OsStr> {
+        let mut names: Vec<&OsStr> = self
+            .added
+            .keys()
+            .chain(self.removed.keys())
+            .chain(self.changed.keys())
+            .map(OsString::as_os_str)
+            .collect();
+        names.sort();
+        names.into_iter()
+    }
+}
+
+/// Lists one variable per line, prefixed with `+` when it was
+/// added, `-` when it was removed and `~` when it was changed.
+impl fmt::Display for EnvDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for name in self.names() {
+            if let Some(value) = self.added.get(name) {
+                writeln!(f, "+ {:?}={:?}", name, value)?;
+            } else if let Some(value) = self.removed.get(name) {
+                writeln!(f, "- {:?}={:?}", name, value)?;
+            } else if let Some((old_value, new_value)) = self.changed.get(name) {
+                writeln!(f, "~ {:?}={:?} -> {:?}", name, old_value, new_value)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// A rust lifetime scope for the whole environment. When an
+/// instance goes out of scope the environment is put back to
+/// exactly the state it was in when the instance was created,
+/// including variables that were changed without a `ScopedEnv`.
+///
+/// Like `ScopedEnv`, every instance holds the process-wide
+/// environment lock until it is dropped.
+pub struct ScopedEnvSnapshot {
+    snapshot: EnvSnapshot,
+    _lock: EnvLock,
+}
+
+impl ScopedEnvSnapshot {
+    /// Captures the current environment. The returned instance
+    /// should be assigned to a `_name` binding so that it lasts
+    /// as long as the current block.
+    ///
+    /// ```rust
+    /// use scoped_env::ScopedEnvSnapshot;
+    /// {
+    ///     let _c = ScopedEnvSnapshot::new();
+    ///     std::env::set_var("HELLO", "WORLD");
+    /// }
+    /// assert!(std::env::var("HELLO").is_err());
+    /// ```
+    pub fn new() -> Self {
+        let _lock = lock();
+        Self {
+            snapshot: EnvSnapshot::capture(),
+            _lock,
+        }
+    }
+
+    /// The environment as it was when this instance was created.
+    pub fn snapshot(&self) -> &EnvSnapshot {
+        &self.snapshot
+    }
+}
+
+impl Default for ScopedEnvSnapshot {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for ScopedEnvSnapshot {
+    fn drop(&mut self) {
+        // The instance may have moved to another thread, which would
+        // wait forever on the lock it holds in `_lock`.
+        self.snapshot.restore_locked();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn does_capture() {
+        let _lock = lock();
+        env::set_var("SNAPSHOT_A", "hello");
+        let snapshot = EnvSnapshot::capture();
+        env::remove_var("SNAPSHOT_A");
+
+        assert_eq!(snapshot.get("SNAPSHOT_A").unwrap(), "hello");
+        assert!(snapshot.iter().any(|(name, _)| name == "SNAPSHOT_A"));
+    }
+
+    #[test]
+    fn does_restore_everything_at_end_of_block() {
+        let _lock = lock();
+        env::set_var("SNAPSHOT_B", "changed");
+        env::set_var("SNAPSHOT_C", "removed");
+        env::remove_var("SNAPSHOT_D");
+        {
+            let _c = ScopedEnvSnapshot::new();
+            env::set_var("SNAPSHOT_B", "new value");
+            env::remove_var("SNAPSHOT_C");
+            env::set_var("SNAPSHOT_D", "added");
+        }
+
+        assert_eq!(env::var("SNAPSHOT_B").unwrap(), "changed");
+        assert_eq!(env::var("SNAPSHOT_C").unwrap(), "removed");
+        assert_eq!(env::var_os("SNAPSHOT_D"), None);
+        env::remove_var("SNAPSHOT_B");
+        env::remove_var("SNAPSHOT_C");
+    }
+
+    #[test]
+    fn does_restore_when_dropped_on_another_thread() {
+        let _lock = lock();
+        env::set_var("SNAPSHOT_E", "kept");
+        let guard = ScopedEnvSnapshot::new();
+        env::set_var("SNAPSHOT_E", "changed");
+        std::thread::spawn(move || drop(guard)).join().unwrap();
+
+        assert_eq!(env::var("SNAPSHOT_E").unwrap(), "kept");
+        env::remove_var("SNAPSHOT_E");
+    }
+
+    #[test]
+    fn does_diff() {
+        let _lock = lock();
+        env::set_var("DIFF_CHANGED", "old");
+        env::set_var("DIFF_REMOVED", "gone");
+        env::remove_var("DIFF_ADDED");
+        let before = EnvSnapshot::capture();
+        let after = {
+            let _c = ScopedEnvSnapshot::new();
+            env::set_var("DIFF_CHANGED", "new");
+            env::remove_var("DIFF_REMOVED");
+            env::set_var("DIFF_ADDED", "here");
+            EnvSnapshot::capture()
+        };
+
+        let diff = before.diff(&after);
+        assert!(!diff.is_empty());
+        assert_eq!(
+            diff.names().collect::<Vec<_>>(),
+            vec!["DIFF_ADDED", "DIFF_CHANGED", "DIFF_REMOVED"]
+        );
+        assert_eq!(
+            diff.to_string(),
+            "+ \"DIFF_ADDED\"=\"here\"\n\
+             ~ \"DIFF_CHANGED\"=\"old\" -> \"new\"\n\
+             - \"DIFF_REMOVED\"=\"gone\"\n"
+        );
+        assert!(before.diff(&EnvSnapshot::capture()).is_empty());
+        env::remove_var("DIFF_CHANGED");
+        env::remove_var("DIFF_REMOVED");
+    }
+}
@@ -0,0 +1,92 @@
+use std::ffi::OsStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::thread;
+
+/// What a guard does when it finds that the variable it manages
+/// was changed by someone else while it was in scope.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TamperPolicy {
+    /// Restore the previous value without saying anything.
+    #[default]
+    Restore,
+    /// Restore the previous value and print a warning to stderr.
+    Warn,
+    /// Restore the previous value and then panic with the
+    /// expected and found values. If the thread is already
+    /// panicking a warning is printed instead.
+    Panic,
+}
+
+static DEFAULT_POLICY: AtomicU8 = AtomicU8::new(TamperPolicy::Restore as u8);
+
+/// Sets the [`TamperPolicy`] used by every guard that has not been
+/// given one of its own.
+///
+/// ```rust
+/// use scoped_env::{set_tamper_policy, TamperPolicy};
+/// set_tamper_policy(TamperPolicy::Panic);
+/// ```
+pub fn set_tamper_policy(policy: TamperPolicy) {
+    DEFAULT_POLICY.store(policy as u8, Ordering::Relaxed);
+}
+
+/// The [`TamperPolicy`] used by every guard that has not been given
+/// one of its own.
+pub fn tamper_policy() -> TamperPolicy {
+    match DEFAULT_POLICY.load(Ordering::Relaxed) {
+        policy if policy == TamperPolicy::Warn as u8 => TamperPolicy::Warn,
+        policy if policy == TamperPolicy::Panic as u8 => TamperPolicy::Panic,
+        _ => TamperPolicy::Restore,
+    }
+}
+
+/// A report of a variable that no longer had the value its guard
+/// set. Call [`Tampered::report`] once the variable has been
+/// restored.
+pub(crate) struct Tampered {
+    message: String,
+    policy: TamperPolicy,
+}
+
+/// Compares the {found} value of {name} with the one its guard
+/// {expected}.
+pub(crate) fn check(
+    name: &OsStr,
+    expected: Option<&OsStr>,
+    found: Option<&OsStr>,
+    policy: Option<TamperPolicy>,
+) -> Option<Tampered> {
+    if expected == found {
+        return None;
+    }
+
+    let policy = policy.unwrap_or_else(tamper_policy);
+    if policy == TamperPolicy::Restore {
+        return None;
+    }
+
+    let message = format!(
+        "environment variable {:?} was changed while scoped: expected {}, found {}",
+        name,
+        describe(expected),
+        describe(found)
+    );
+    Some(Tampered { message, policy })
+}
+
+impl Tampered {
+    pub(crate) fn report(self) {
+        if self.policy == TamperPolicy::Panic && !thread::panicking() {
+            panic!("{}", self.message);
+        }
+
+        eprintln!("warning: {}", self.message);
+    }
+}
+
+fn describe(value: Option<&OsStr>) -> String {
+    match value {
+        Some(value) => format!("{:?}", value),
+        None => "unset".into(),
+    }
+}
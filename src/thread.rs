@@ -0,0 +1,262 @@
+//! Threads that inherit the scoped environment of the thread that
+//! spawned them.
+//!
+//! A plain `std::thread::spawn` loses both the
+//! [`overlay`] of the spawning thread and its hold
+//! on the environment lock, so a helper thread that creates a
+//! `ScopedEnv` would wait for the spawning thread's guards to go out
+//! of scope. Threads started from this module see the same overlay
+//! and, if the spawning thread holds the lock, share that hold and
+//! keep it alive until they finish, so no other thread can take the
+//! lock while they run.
+//!
+//! ```rust
+//! use scoped_env::{overlay, thread, ScopedEnv};
+//!
+//! let _c = ScopedEnv::set("HELLO", "WORLD");
+//! let _d = overlay::set("LOG_LEVEL", "debug");
+//! thread::spawn(|| {
+//!     let _e = ScopedEnv::set("HELPER", "1");
+//!     assert_eq!(overlay::var("LOG_LEVEL").unwrap().as_str(), "debug");
+//! })
+//! .join()
+//! .unwrap();
+//! ```
+
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::io;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use crate::lock::{self, EnvLock, OwnerGuard};
+use crate::overlay::{self, OverlayGuard};
+
+/// The scoped environment of a thread: its overlaid variables and
+/// its hold on the environment lock, if it has one. A context can be
+/// captured on one thread and entered on others, which is how
+/// thread pools can give each worker the environment of the thread
+/// that created the pool.
+///
+/// A context captured while the lock is held keeps its own hold on
+/// the lock until the context and all its clones are dropped, even
+/// if the guards of the capturing thread go out of scope first.
+/// Other threads wait for that, so a context kept by a long-lived
+/// pool keeps the lock for as long as the pool keeps the context.
+///
+/// ```rust
+/// use scoped_env::{overlay, thread::EnvContext};
+///
+/// let _c = overlay::set("LOG_LEVEL", "debug");
+/// let context = EnvContext::current();
+/// std::thread::spawn(move || {
+///     context.run(|| assert_eq!(overlay::var("LOG_LEVEL").unwrap().as_str(), "debug"))
+/// })
+/// .join()
+/// .unwrap();
+/// ```
+///
+/// With rayon, wrap each worker in a pool's `spawn_handler`:
+///
+/// ```rust,ignore
+/// let context = EnvContext::current();
+/// let pool = rayon::ThreadPoolBuilder::new()
+///     .spawn_handler(move |worker| {
+///         let context = context.clone();
+///         std::thread::Builder::new().spawn(move || context.run(|| worker.run()))?;
+///         Ok(())
+///     })
+///     .build()?;
+/// ```
+#[derive(Debug, Clone)]
+pub struct EnvContext {
+    overlay: BTreeMap<OsString, Option<OsString>>,
+    hold: Option<Arc<Hold>>,
+}
+
+/// A share of the environment lock held under the identity of the
+/// thread that captured a context.
+#[derive(Debug)]
+struct Hold {
+    owner: u64,
+    _lock: EnvLock,
+}
+
+/// Keeps an [`EnvContext`] installed on the current thread until it
+/// goes out of scope.
+pub struct EnvContextGuard {
+    _overlay: OverlayGuard,
+    _owner: Option<OwnerGuard>,
+}
+
+impl EnvContext {
+    /// Captures the scoped environment of the current thread.
+    pub fn current() -> Self {
+        let hold = lock::held_owner().map(|owner| {
+            Arc::new(Hold {
+                owner,
+                // Reentrant, so this only adds to the current hold.
+                _lock: lock::lock(),
+            })
+        });
+        Self {
+            overlay: overlay::current(),
+            hold,
+        }
+    }
+
+    /// Installs this context on the current thread until the
+    /// returned instance goes out of scope.
+    pub fn enter(&self) -> EnvContextGuard {
+        EnvContextGuard {
+            _overlay: overlay::push(self.overlay.clone()),
+            _owner: self.hold.as_ref().map(|hold| lock::enter_owner(hold.owner)),
+        }
+    }
+
+    /// Runs {body} with this context installed on the current
+    /// thread.
+    pub fn run<R>(&self, body: impl FnOnce() -> R) -> R {
+        let _context = self.enter();
+        body()
+    }
+}
+
+/// Spawns a new thread that inherits the scoped environment of the
+/// current thread. See [`std::thread::spawn`].
+///
+/// # Panics
+///
+/// Panics if the operating system fails to create a thread.
+pub fn spawn<F, T>(body: F) -> JoinHandle<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    Builder::new().spawn(body).expect("failed to spawn thread")
+}
+
+/// A [`std::thread::Builder`] whose threads inherit the scoped
+/// environment of the thread that spawns them.
+#[derive(Debug)]
+pub struct Builder {
+    inner: thread::Builder,
+}
+
+impl Builder {
+    /// Creates a builder with the default configuration.
+    pub fn new() -> Self {
+        thread::Builder::new().into()
+    }
+
+    /// Names the thread. See [`std::thread::Builder::name`].
+    pub fn name(self, name: String) -> Self {
+        self.inner.name(name).into()
+    }
+
+    /// Sets the stack size of the thread. See
+    /// [`std::thread::Builder::stack_size`].
+    pub fn stack_size(self, size: usize) -> Self {
+        self.inner.stack_size(size).into()
+    }
+
+    /// Spawns a thread that runs {body} with the scoped environment
+    /// of the current thread installed. See
+    /// [`std::thread::Builder::spawn`].
+    pub fn spawn<F, T>(self, body: F) -> io::Result<JoinHandle<T>>
+    where
+        F: FnOnce() -> T + Send + 'static,
+        T: Send + 'static,
+    {
+        let context = EnvContext::current();
+        self.inner.spawn(move || context.run(body))
+    }
+}
+
+impl Default for Builder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl From<thread::Builder> for Builder {
+    fn from(inner: thread::Builder) -> Self {
+        Self { inner }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ScopedEnv;
+    use std::env;
+
+    #[test]
+    fn does_inherit_overlay() {
+        let _c = overlay::set("THREAD_A", "hello");
+        let handle = Builder::new()
+            .name("inherits".into())
+            .spawn(|| {
+                (
+                    thread::current().name().map(String::from),
+                    overlay::var_os("THREAD_A"),
+                )
+            })
+            .unwrap();
+
+        assert_eq!(
+            handle.join().unwrap(),
+            (Some("inherits".into()), Some("hello".into()))
+        );
+    }
+
+    #[test]
+    fn does_share_the_lock() {
+        let _c = ScopedEnv::set("THREAD_C", "hello");
+        let value = spawn(|| {
+            let _d = ScopedEnv::set("THREAD_D", "from child");
+            (env::var("THREAD_C").unwrap(), env::var("THREAD_D").unwrap())
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(value, ("hello".into(), "from child".into()));
+        assert_eq!(env::var_os("THREAD_D"), None);
+    }
+
+    #[test]
+    fn context_can_be_entered_on_other_threads() {
+        let _c = overlay::set("THREAD_E", "hello");
+        let context = EnvContext::current();
+        let value = thread::spawn(move || {
+            let before = overlay::var_os("THREAD_E");
+            let during = context.run(|| overlay::var_os("THREAD_E"));
+            (before, during, overlay::var_os("THREAD_E"))
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(value, (None, Some("hello".into()), None));
+    }
+
+    #[test]
+    fn context_blocks_other_threads_while_alive() {
+        use std::sync::mpsc;
+        use std::time::Duration;
+
+        let c = ScopedEnv::set("THREAD_H", "hello");
+        let context = EnvContext::current();
+        drop(c);
+
+        let (tx, rx) = mpsc::channel();
+        let other = thread::spawn(move || {
+            let _d = lock::lock();
+            tx.send(()).unwrap();
+        });
+
+        assert!(rx.recv_timeout(Duration::from_millis(50)).is_err());
+        drop(context);
+        rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        other.join().unwrap();
+    }
+}
@@ -0,0 +1,132 @@
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fmt::Display;
+use std::str::FromStr;
+
+use crate::{ParseVarError, ScopedEnv, ScopedEnvError};
+
+impl<T> ScopedEnv<T>
+where
+    T: AsRef<OsStr>,
+{
+    /// Sets the environment variable {name} to the `Display` form
+    /// of {value}.
+    ///
+    /// ```rust
+    /// use scoped_env::ScopedEnv;
+    /// let c = ScopedEnv::set_value("PORT", 8080u16);
+    /// assert_eq!(std::env::var(&c).unwrap().as_str(), "8080");
+    /// assert_eq!(c.get::<u16>().unwrap(), 8080);
+    /// ```
+    ///
+    /// # Panics
+    ///
+    /// Panics in the same cases as [`ScopedEnv::set`]. Use
+    /// [`ScopedEnv::try_set_value`] to handle these as errors
+    /// instead.
+    pub fn set_value<V: Display>(name: T, value: V) -> Self {
+        Self::set(name, value.to_string())
+    }
+
+    /// Sets the environment variable {name} to the `Display` form
+    /// of {value}, returning an error instead of panicking if
+    /// either is not valid for the environment.
+    pub fn try_set_value<V: Display>(name: T, value: V) -> Result<Self, ScopedEnvError> {
+        Self::try_set(name, value.to_string())
+    }
+
+    /// Parses the current value of the variable this instance
+    /// scopes.
+    pub fn get<U: FromStr>(&self) -> Result<U, ParseVarError<U::Err>> {
+        var_as(self.name.as_ref())
+    }
+}
+
+/// Fetches the environment variable {name} and parses it with
+/// `FromStr`. Errors name the variable and, where there is one, the
+/// value that could not be parsed.
+///
+/// ```rust
+/// use scoped_env::{var_as, ScopedEnv};
+/// let _c = ScopedEnv::set("DEBUG", "yes");
+/// let err = var_as::<bool>("DEBUG").unwrap_err();
+/// assert_eq!(
+///     err.to_string(),
+///     r#"value "yes" for environment variable "DEBUG" is invalid: provided string was not `true` or `false`"#
+/// );
+/// ```
+pub fn var_as<U: FromStr>(name: impl AsRef<OsStr>) -> Result<U, ParseVarError<U::Err>> {
+    let name = name.as_ref();
+    let value = env::var_os(name).ok_or_else(|| ParseVarError::NotPresent { name: name.into() })?;
+    let value = value
+        .into_string()
+        .map_err(|value| ParseVarError::NotUnicode {
+            name: name.into(),
+            value,
+        })?;
+
+    value.parse().map_err(|source| ParseVarError::Invalid {
+        name: OsString::from(name),
+        value,
+        source,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lock;
+    use std::error::Error;
+    use std::num::ParseIntError;
+
+    #[test]
+    fn does_set_display_values() {
+        let c = ScopedEnv::set_value("VALUE_A", 8080u16);
+        assert_eq!(env::var(&c).unwrap(), "8080");
+        assert_eq!(c.get::<u16>().unwrap(), 8080);
+
+        let c = ScopedEnv::set_value("VALUE_B", true);
+        assert!(c.get::<bool>().unwrap());
+        assert_eq!(c.get::<String>().unwrap(), "true");
+    }
+
+    #[test]
+    fn does_report_the_name_and_value() {
+        let _c = ScopedEnv::set("VALUE_C", "eighty");
+        let err = var_as::<u16>("VALUE_C").unwrap_err();
+        assert_eq!(err.name(), "VALUE_C");
+        assert_eq!(
+            err.to_string(),
+            r#"value "eighty" for environment variable "VALUE_C" is invalid: invalid digit found in string"#
+        );
+        assert!(err.source().unwrap().is::<ParseIntError>());
+    }
+
+    #[test]
+    fn does_report_missing_variables() {
+        let _lock = lock();
+        env::remove_var("VALUE_D");
+        assert_eq!(
+            var_as::<u16>("VALUE_D"),
+            Err(ParseVarError::NotPresent {
+                name: "VALUE_D".into()
+            })
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn does_report_non_unicode_values() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let value = OsStr::from_bytes(b"\xff");
+        let _c = ScopedEnv::set("VALUE_E", value);
+        assert_eq!(
+            var_as::<String>("VALUE_E"),
+            Err(ParseVarError::NotUnicode {
+                name: "VALUE_E".into(),
+                value: value.into()
+            })
+        );
+    }
+}